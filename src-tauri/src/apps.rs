@@ -0,0 +1,108 @@
+use crate::config::AppTarget;
+use std::process::Command;
+
+/// Transforms a web URL into the application's own URI using its template,
+/// e.g. `https://open.spotify.com/track/X` into `spotify:track:X`.
+/// Returns None if the URL doesn't match the application's pattern.
+pub fn transform_url(url: &str, app: &AppTarget) -> Option<String> {
+    let re = regex::Regex::new(&app.pattern).ok()?;
+    let captures = re.captures(url)?;
+
+    let mut uri = String::new();
+    captures.expand(&app.template, &mut uri);
+    Some(uri)
+}
+
+/// Launches the application with a URI. If the target has no command, the URI
+/// is handed to the operating system, which opens it in the registered app.
+pub fn launch(app: &AppTarget, uri: &str) -> Result<(), String> {
+    if !app.command.is_empty() {
+        Command::new(&app.command)
+            .arg(uri)
+            .spawn()
+            .map_err(|e| format!("Failed to open application '{}': {}", app.name, e))?;
+        return Ok(());
+    }
+
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        // `cmd /C start` would mangle URIs containing `&`, so use the shell's protocol handler
+        let mut command = Command::new("rundll32");
+        command.args(["url.dll,FileProtocolHandler", uri]);
+        command
+    };
+
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut command = Command::new("open");
+        command.arg(uri);
+        command
+    };
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = {
+        let mut command = Command::new("xdg-open");
+        command.arg(uri);
+        command
+    };
+
+    command
+        .spawn()
+        .map_err(|e| format!("Failed to open application '{}': {}", app.name, e))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(pattern: &str, template: &str) -> AppTarget {
+        AppTarget {
+            id: "app".to_string(),
+            name: "App".to_string(),
+            pattern: pattern.to_string(),
+            template: template.to_string(),
+            command: String::new(),
+        }
+    }
+
+    #[test]
+    fn transforms_urls_with_numbered_and_named_captures() {
+        let spotify = app(
+            r"^https://open\.spotify\.com/(track|album)/(\w+)",
+            "spotify:$1:$2",
+        );
+        assert_eq!(
+            transform_url("https://open.spotify.com/track/4uLU6hMC?si=x", &spotify).as_deref(),
+            Some("spotify:track:4uLU6hMC")
+        );
+
+        let zoom = app(
+            r"^https://(?:[\w-]+\.)?zoom\.us/j/(?<id>\d+)(?:\?pwd=(?<pwd>\w+))?",
+            "zoommtg://zoom.us/join?confno=${id}&pwd=${pwd}",
+        );
+        assert_eq!(
+            transform_url("https://acme.zoom.us/j/123456?pwd=abc", &zoom).as_deref(),
+            Some("zoommtg://zoom.us/join?confno=123456&pwd=abc")
+        );
+        // Groups that didn't take part in the match are empty
+        assert_eq!(
+            transform_url("https://zoom.us/j/123456", &zoom).as_deref(),
+            Some("zoommtg://zoom.us/join?confno=123456&pwd=")
+        );
+    }
+
+    #[test]
+    fn leaves_other_urls_and_invalid_patterns_alone() {
+        let spotify = app(
+            r"^https://open\.spotify\.com/track/(\w+)",
+            "spotify:track:$1",
+        );
+        assert_eq!(transform_url("https://example.com/track/1", &spotify), None);
+        assert_eq!(
+            transform_url("https://open.spotify.com/track/1", &app("(", "x")),
+            None
+        );
+    }
+}
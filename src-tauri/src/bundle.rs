@@ -0,0 +1,584 @@
+use crate::config::{browser_is_installed, known_browser_paths, Config};
+use crate::icons::{self, PreparedIcon};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+/// Identifies a file as an export from this app.
+const BUNDLE_FORMAT: &str = "pick-browser-export";
+
+/// Version of the export format. Bump it when older versions of the app could
+/// no longer read an export correctly; exports from newer versions are refused.
+const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    pub app_version: String, // Version of the app that made the export
+    pub exported_at: u64,    // Unix timestamp, in seconds
+}
+
+/// Everything needed to set the app up on another machine, in a single file:
+/// the config and the browser icons it refers to.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Bundle {
+    pub manifest: Manifest,
+    pub config: Config,
+    #[serde(default)]
+    pub icons: BTreeMap<String, String>, // Base64-encoded icon files, keyed by file name
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    #[default]
+    Merge, // Add what isn't set up here yet, keeping everything that is
+    Replace, // Replace the config and icons entirely
+}
+
+/// What an import changed, or would change.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub added: Vec<String>,              // e.g. "Browser 'Firefox'"
+    pub skipped: Vec<String>, // Entries already set up here, or that couldn't be imported
+    pub renamed_ids: Vec<String>, // Entries given a new id, as theirs is already taken here
+    pub remapped_paths: Vec<String>, // Browsers found at a different path on this machine
+    pub missing_paths: Vec<MissingPath>, // Browsers whose executable isn't on this machine
+}
+
+/// A browser whose executable wasn't found, so needs a path for this machine.
+#[derive(Debug, Clone, Serialize)]
+pub struct MissingPath {
+    pub browser_id: String, // ID in the export, which `path_remaps` are keyed by
+    pub name: String,
+    pub path: String,
+}
+
+/// The result of importing a bundle: the new config, and icons still to be
+/// saved for the browsers that were added, keyed by their id in that config.
+pub struct Import {
+    pub config: Config,
+    pub icons: Vec<(String, PreparedIcon)>,
+    pub report: ImportReport,
+}
+
+/// Bundles the config with the icon files of its browsers.
+pub fn export(config: Config, icons_dir: &Path, exported_at: u64) -> Result<Bundle, String> {
+    use base64::Engine;
+
+    let mut icons = BTreeMap::new();
+    for browser in &config.browsers {
+        let Some(ext) = &browser.icon else {
+            continue;
+        };
+        let name = format!("{}.{}", browser.id, ext);
+        // A missing icon is left out, and the browser imported without one
+        let Ok(bytes) = std::fs::read(icons_dir.join(&name)) else {
+            continue;
+        };
+        icons.insert(
+            name,
+            base64::engine::general_purpose::STANDARD.encode(bytes),
+        );
+    }
+
+    Ok(Bundle {
+        manifest: Manifest {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            exported_at,
+        },
+        config,
+        icons,
+    })
+}
+
+/// Reads an exported file, checking it is an export this version can read.
+pub fn parse(content: &str) -> Result<Bundle, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(content).map_err(|e| format!("Not a valid export file: {}", e))?;
+
+    // The manifest is checked first, as a newer config may not parse at all
+    let manifest: Manifest = serde_json::from_value(value["manifest"].clone())
+        .map_err(|_| "Not an export file: it has no manifest".to_string())?;
+    if manifest.format != BUNDLE_FORMAT {
+        return Err(format!(
+            "Not an export file: unknown format '{}'",
+            manifest.format
+        ));
+    }
+    if manifest.version > BUNDLE_VERSION {
+        return Err(format!(
+            "This export was made by a newer version of the app ({}), so can't be imported",
+            manifest.app_version
+        ));
+    }
+
+    serde_json::from_value(value).map_err(|e| format!("Failed to read export file: {}", e))
+}
+
+/// Imports a bundle into the `local` config. `path_remaps` gives new paths for
+/// browsers whose executable is somewhere else on this machine, keyed by their
+/// id in the bundle; browsers that still can't be found, but are known browsers
+/// installed elsewhere here, are pointed there.
+///
+/// Replacing takes the bundle's config as it is. Merging keeps everything
+/// already set up: browsers, applications and groups with the same name as one
+/// here are taken to be that one, rules and rewrites already here are skipped,
+/// and anything else is added, with a new id if its own is already taken.
+/// References between entries are updated to match.
+pub fn import(
+    local: Config,
+    bundle: Bundle,
+    mode: ImportMode,
+    path_remaps: &HashMap<String, String>,
+) -> Import {
+    let mut report = ImportReport::default();
+    let mut incoming = bundle.config;
+
+    for browser in &mut incoming.browsers {
+        if let Some(path) = path_remaps
+            .get(&browser.id)
+            .filter(|p| !p.trim().is_empty())
+        {
+            browser.path = path.trim().to_string();
+        }
+    }
+
+    // (id in the bundle, id here) for each browser that is added
+    let mut added_browsers: Vec<(String, String)> = Vec::new();
+    let mut config = match mode {
+        ImportMode::Replace => {
+            for browser in &incoming.browsers {
+                added_browsers.push((browser.id.clone(), browser.id.clone()));
+                report.added.push(format!("Browser '{}'", browser.name));
+            }
+            for rule in &incoming.rules {
+                report.added.push(format!("Rule '{}'", rule.pattern));
+            }
+            incoming
+        }
+        ImportMode::Merge => merge(local, incoming, &mut added_browsers, &mut report),
+    };
+
+    let mut icons = Vec::new();
+    for (bundle_id, id) in &added_browsers {
+        let Some(browser) = config.browsers.iter_mut().find(|b| b.id == *id) else {
+            continue;
+        };
+
+        if !browser_is_installed(&browser.path) {
+            let known = known_browser_paths().into_iter().find(|(name, path)| {
+                name.eq_ignore_ascii_case(&browser.name) && browser_is_installed(path)
+            });
+            match known {
+                Some((_, path)) => {
+                    report.remapped_paths.push(format!(
+                        "Browser '{}': {} to {}",
+                        browser.name, browser.path, path
+                    ));
+                    browser.path = path.to_string();
+                }
+                None => report.missing_paths.push(MissingPath {
+                    browser_id: bundle_id.clone(),
+                    name: browser.name.clone(),
+                    path: browser.path.clone(),
+                }),
+            }
+        }
+
+        // The icon is set again once it has been saved
+        let Some(ext) = browser.icon.take() else {
+            continue;
+        };
+        match bundled_icon(&bundle.icons, bundle_id, &ext) {
+            Ok(Some(prepared)) => icons.push((id.clone(), prepared)),
+            Ok(None) => {}
+            Err(e) => report
+                .skipped
+                .push(format!("Icon of browser '{}': {}", browser.name, e)),
+        }
+    }
+
+    Import {
+        config,
+        icons,
+        report,
+    }
+}
+
+/// Decodes and checks a browser's icon from a bundle, as if it had just been uploaded.
+fn bundled_icon(
+    icons: &BTreeMap<String, String>,
+    browser_id: &str,
+    ext: &str,
+) -> Result<Option<PreparedIcon>, String> {
+    let Some(data) = icons.get(&format!("{}.{}", browser_id, ext)) else {
+        return Ok(None);
+    };
+    icons::prepare_upload(data, icons::mime_for_extension(ext)).map(Some)
+}
+
+/// Gives an incoming entry an id that isn't taken here, keeping its own if possible.
+fn free_id(
+    id: &str,
+    taken: &mut HashSet<String>,
+    description: String,
+    report: &mut ImportReport,
+) -> String {
+    let new_id = if id.is_empty() || taken.contains(id) {
+        report.renamed_ids.push(description);
+        cuid2::create_id()
+    } else {
+        id.to_string()
+    };
+    taken.insert(new_id.clone());
+    new_id
+}
+
+fn merge(
+    mut config: Config,
+    incoming: Config,
+    added_browsers: &mut Vec<(String, String)>,
+    report: &mut ImportReport,
+) -> Config {
+    // Browsers, matched by name
+    let mut browser_ids: HashMap<String, String> = HashMap::new();
+    let mut taken: HashSet<String> = config.browsers.iter().map(|b| b.id.clone()).collect();
+    for mut browser in incoming.browsers {
+        if let Some(existing) = config
+            .browsers
+            .iter()
+            .find(|b| b.name.eq_ignore_ascii_case(&browser.name))
+        {
+            browser_ids.insert(browser.id, existing.id.clone());
+            report
+                .skipped
+                .push(format!("Browser '{}' is already set up", browser.name));
+            continue;
+        }
+        let id = free_id(
+            &browser.id,
+            &mut taken,
+            format!("Browser '{}'", browser.name),
+            report,
+        );
+        browser_ids.insert(browser.id.clone(), id.clone());
+        added_browsers.push((browser.id, id.clone()));
+        report.added.push(format!("Browser '{}'", browser.name));
+        browser.id = id;
+        config.browsers.push(browser);
+    }
+
+    // Applications, matched by name
+    let mut app_ids: HashMap<String, String> = HashMap::new();
+    let mut taken: HashSet<String> = config.apps.iter().map(|a| a.id.clone()).collect();
+    for mut app in incoming.apps {
+        if let Some(existing) = config
+            .apps
+            .iter()
+            .find(|a| a.name.eq_ignore_ascii_case(&app.name))
+        {
+            app_ids.insert(app.id, existing.id.clone());
+            report
+                .skipped
+                .push(format!("Application '{}' is already set up", app.name));
+            continue;
+        }
+        let id = free_id(
+            &app.id,
+            &mut taken,
+            format!("Application '{}'", app.name),
+            report,
+        );
+        app_ids.insert(app.id, id.clone());
+        report.added.push(format!("Application '{}'", app.name));
+        app.id = id;
+        config.apps.push(app);
+    }
+
+    // Groups, matched by name, gaining any browsers they don't have yet
+    let mut group_ids: HashMap<String, String> = HashMap::new();
+    let mut taken: HashSet<String> = config.groups.iter().map(|g| g.id.clone()).collect();
+    for mut group in incoming.groups {
+        let browsers: Vec<String> = group
+            .browser_ids
+            .iter()
+            .filter_map(|id| browser_ids.get(id).cloned())
+            .collect();
+        if let Some(existing) = config
+            .groups
+            .iter_mut()
+            .find(|g| g.name.eq_ignore_ascii_case(&group.name))
+        {
+            group_ids.insert(group.id, existing.id.clone());
+            for id in browsers {
+                if !existing.browser_ids.contains(&id) {
+                    existing.browser_ids.push(id);
+                }
+            }
+            report
+                .skipped
+                .push(format!("Browser group '{}' is already set up", group.name));
+            continue;
+        }
+        let id = free_id(
+            &group.id,
+            &mut taken,
+            format!("Browser group '{}'", group.name),
+            report,
+        );
+        group_ids.insert(group.id, id.clone());
+        report.added.push(format!("Browser group '{}'", group.name));
+        group.id = id;
+        group.browser_ids = browsers;
+        config.groups.push(group);
+    }
+
+    // Rules, after the ones here so those still take priority
+    let mut taken: HashSet<String> = config.rules.iter().map(|r| r.id.clone()).collect();
+    for mut rule in incoming.rules {
+        let (Some(browser_id), Some(app_id), Some(group_id)) = (
+            map_id(&browser_ids, &rule.browser_id),
+            map_id(&app_ids, &rule.app_id),
+            map_id(&group_ids, &rule.group_id),
+        ) else {
+            report.skipped.push(format!(
+                "Rule '{}' opens in something that isn't in the export",
+                rule.pattern
+            ));
+            continue;
+        };
+        rule.browser_id = browser_id;
+        rule.app_id = app_id;
+        rule.group_id = group_id;
+
+        let exists = config.rules.iter().any(|r| {
+            r.pattern == rule.pattern
+                && r.browser_id == rule.browser_id
+                && r.app_id == rule.app_id
+                && r.group_id == rule.group_id
+        });
+        if exists {
+            report
+                .skipped
+                .push(format!("Rule '{}' already exists", rule.pattern));
+            continue;
+        }
+        rule.id = free_id(
+            &rule.id,
+            &mut taken,
+            format!("Rule '{}'", rule.pattern),
+            report,
+        );
+        report.added.push(format!("Rule '{}'", rule.pattern));
+        config.rules.push(rule);
+    }
+
+    let mut taken: HashSet<String> = config.rewrites.iter().map(|r| r.id.clone()).collect();
+    for mut rewrite in incoming.rewrites {
+        let exists = config
+            .rewrites
+            .iter()
+            .any(|r| r.pattern == rewrite.pattern && r.replacement == rewrite.replacement);
+        if exists {
+            continue;
+        }
+        rewrite.id = free_id(
+            &rewrite.id,
+            &mut taken,
+            format!("Rewrite '{}'", rewrite.pattern),
+            report,
+        );
+        report.added.push(format!("Rewrite '{}'", rewrite.pattern));
+        config.rewrites.push(rewrite);
+    }
+
+    for unwrapper in incoming.unwrappers {
+        let exists = config
+            .unwrappers
+            .iter()
+            .any(|u| u.pattern == unwrapper.pattern && u.param == unwrapper.param);
+        if !exists {
+            report
+                .added
+                .push(format!("Redirect wrapper '{}'", unwrapper.pattern));
+            config.unwrappers.push(unwrapper);
+        }
+    }
+
+    // Lists of settings gain the entries they don't have; other settings stay as they are here
+    let union = |into: &mut Vec<String>, from: Vec<String>| {
+        for value in from {
+            if !into.contains(&value) {
+                into.push(value);
+            }
+        }
+    };
+    union(&mut config.schemes, incoming.schemes);
+    union(&mut config.tracking.params, incoming.tracking.params);
+    union(
+        &mut config.tracking.exceptions,
+        incoming.tracking.exceptions,
+    );
+    union(&mut config.short_links.hosts, incoming.short_links.hosts);
+    union(
+        &mut config.suggestions.ignored_origins,
+        incoming.suggestions.ignored_origins,
+    );
+    if config.fallback.browser_id.is_empty() {
+        match map_id(&browser_ids, &incoming.fallback.browser_id) {
+            Some(id) => config.fallback.browser_id = id,
+            None => report.skipped.push(format!(
+                "Fallback browser '{}' isn't in the export",
+                incoming.fallback.browser_id
+            )),
+        }
+    }
+
+    config
+}
+
+/// Maps an id from the export to its id here. An empty id means "none", so
+/// stays empty; None means it refers to something that isn't in the export.
+fn map_id(ids: &HashMap<String, String>, id: &str) -> Option<String> {
+    if id.is_empty() {
+        return Some(String::new());
+    }
+    ids.get(id).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(value: serde_json::Value) -> Config {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn bundle(config: Config) -> Bundle {
+        export(config, Path::new("/nonexistent"), 0).unwrap()
+    }
+
+    #[test]
+    fn parse_refuses_other_files_and_newer_versions() {
+        let exported = serde_json::to_string(&bundle(Config::default())).unwrap();
+        assert!(parse(&exported).is_ok());
+
+        assert!(parse("{}").unwrap_err().contains("no manifest"));
+        let newer = exported.replace("\"version\":1", "\"version\":2");
+        assert!(parse(&newer).unwrap_err().contains("newer version"));
+        let other = exported.replace(BUNDLE_FORMAT, "something-else");
+        assert!(parse(&other).unwrap_err().contains("unknown format"));
+    }
+
+    #[test]
+    fn merge_matches_by_name_and_renames_colliding_ids() {
+        let local = config(serde_json::json!({
+            "browsers": [
+                {"id": "a", "name": "Firefox", "path": "/local/firefox", "icon": null},
+            ],
+            "rules": [{"id": "r1", "pattern": "*.example.com", "browser_id": "a"}],
+        }));
+        let incoming = config(serde_json::json!({
+            "browsers": [
+                {"id": "x", "name": "firefox", "path": "/other/firefox", "icon": null},
+                {"id": "a", "name": "Work", "path": "/other/work", "icon": null},
+            ],
+            "groups": [{"id": "g", "name": "All", "browser_ids": ["x", "a"]}],
+            "rules": [
+                {"id": "r1", "pattern": "*.example.com", "browser_id": "x"},
+                {"id": "r2", "pattern": "*.work.com", "browser_id": "a"},
+                {"id": "r3", "pattern": "*.test.com", "browser_id": "", "group_id": "g"},
+            ],
+        }));
+
+        let import = import(local, bundle(incoming), ImportMode::Merge, &HashMap::new());
+        let config = import.config;
+
+        // Firefox is the one already here; Work needed a new id, as "a" is taken
+        assert_eq!(config.browsers.len(), 2);
+        let work = &config.browsers[1];
+        assert_ne!(work.id, "a");
+        assert_eq!(import.report.renamed_ids, vec!["Browser 'Work'"]);
+
+        // The rule already here is skipped, and references follow the new ids
+        let patterns: Vec<_> = config.rules.iter().map(|r| r.pattern.as_str()).collect();
+        assert_eq!(patterns, ["*.example.com", "*.work.com", "*.test.com"]);
+        assert_eq!(config.rules[1].browser_id, work.id);
+        assert_eq!(
+            config.groups[0].browser_ids,
+            vec!["a".to_string(), work.id.clone()]
+        );
+        assert_eq!(config.rules[2].group_id, config.groups[0].id);
+
+        // Only the added browser is checked for its path
+        let missing: Vec<_> = import
+            .report
+            .missing_paths
+            .iter()
+            .map(|m| &m.browser_id)
+            .collect();
+        assert_eq!(missing, ["a"]);
+    }
+
+    #[test]
+    fn merge_skips_rules_and_fallback_pointing_outside_the_export() {
+        let local = config(serde_json::json!({
+            "browsers": [{"id": "a", "name": "Firefox", "path": "/local/firefox", "icon": null}],
+        }));
+        let incoming = config(serde_json::json!({
+            "browsers": [{"id": "x", "name": "Firefox", "path": "/other/firefox", "icon": null}],
+            "rules": [
+                {"id": "r1", "pattern": "*.example.com", "browser_id": "gone"},
+                {"id": "r2", "pattern": "*.app.com", "browser_id": "", "app_id": "gone"},
+                {"id": "r3", "pattern": "*.group.com", "browser_id": "", "group_id": "gone"},
+                {"id": "r4", "pattern": "*.prompt.com", "browser_id": ""},
+                {"id": "r5", "pattern": "*.work.com", "browser_id": "x"},
+            ],
+            "fallback": {"browser_id": "gone", "countdown_secs": 5},
+        }));
+
+        let import = import(local, bundle(incoming), ImportMode::Merge, &HashMap::new());
+        let rules: Vec<_> = import
+            .config
+            .rules
+            .iter()
+            .map(|r| (r.pattern.as_str(), r.browser_id.as_str()))
+            .collect();
+        assert_eq!(rules, [("*.prompt.com", ""), ("*.work.com", "a")]);
+        assert!(import.config.fallback.browser_id.is_empty());
+
+        let skipped: Vec<_> = import
+            .report
+            .skipped
+            .iter()
+            .filter(|s| s.contains("isn't in the export"))
+            .collect();
+        assert_eq!(skipped.len(), 4);
+    }
+
+    #[test]
+    fn replace_applies_path_remaps() {
+        let local = config(serde_json::json!({
+            "browsers": [{"id": "a", "name": "Firefox", "path": "/local/firefox", "icon": null}],
+        }));
+        let incoming = config(serde_json::json!({
+            "browsers": [{"id": "x", "name": "Work", "path": "/other/work", "icon": null}],
+        }));
+        let exe = std::env::current_exe()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let remaps = HashMap::from([("x".to_string(), exe.clone())]);
+
+        let import = import(local, bundle(incoming), ImportMode::Replace, &remaps);
+
+        assert_eq!(import.config.browsers.len(), 1);
+        assert_eq!(import.config.browsers[0].id, "x");
+        assert_eq!(import.config.browsers[0].path, exe);
+        assert!(import.report.missing_paths.is_empty());
+    }
+}
@@ -1,3 +1,6 @@
+use crate::config_format::{self, ConfigFormat};
+use crate::desktop_icons;
+use crate::icons::{self, IconFormat};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -10,6 +13,14 @@ pub struct Browser {
     pub name: String,
     pub path: String,
     pub icon: Option<String>, // File extension if icon exists (e.g., "png", "jpg")
+    #[serde(default = "default_true")]
+    pub strip_tracking: bool, // Strip tracking parameters from URLs opened in this browser
+    #[serde(default)]
+    pub schemes: Vec<String>, // URL schemes this target handles (empty = web links and local files)
+    #[serde(default)]
+    pub hidden: bool, // Left out of the picker, but still usable by rules
+    #[serde(default, skip_deserializing, skip_serializing_if = "is_false")]
+    pub locked: bool, // From the system config, so can't be changed or deleted
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,13 +28,165 @@ pub struct Rule {
     pub id: String,
     pub pattern: String,    // Glob pattern to match against URLs (* = wildcard)
     pub browser_id: String, // ID of the browser to open matching URLs in
+    #[serde(default = "default_true")]
+    pub strip_tracking: bool, // Strip tracking parameters from URLs matching this rule
+    #[serde(default)]
+    pub app_id: String, // Application target to open matching URLs in instead (empty = none)
+    #[serde(default)]
+    pub group_id: String, // Browser group to open matching URLs in all at once (empty = none)
+    #[serde(default, skip_deserializing, skip_serializing_if = "is_false")]
+    pub locked: bool, // From the system config, so can't be changed or deleted
+}
+
+/// A named set of browsers that a URL can be opened in all at once, e.g. for cross-browser testing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrowserGroup {
+    pub id: String,
+    pub name: String,
+    pub browser_ids: Vec<String>,
+}
+
+/// A native application that web links can be routed to, e.g. Spotify or Zoom.
+/// Matching URLs are transformed into the application's own URI before launching.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppTarget {
+    pub id: String,
+    pub name: String,
+    pub pattern: String, // Regex matched against the URL, e.g. `^https://zoom\.us/j/(\d+)`
+    pub template: String, // URI built from the captures, e.g. `zoommtg://zoom.us/join?confno=$1`
+    #[serde(default)]
+    pub command: String, // Executable to launch with the URI (empty = the system's handler)
+}
+
+/// Running tally of manual picks for one origin, used to suggest rules.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PickStat {
+    pub origin: String,     // e.g. "https://github.com"
+    pub browser_id: String, // Browser most recently picked for this origin
+    pub count: u32,         // Consecutive manual picks of that browser
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Suggestions {
+    #[serde(default)]
+    pub picks: Vec<PickStat>,
+    #[serde(default)]
+    pub ignored_origins: Vec<String>, // Origins the user never wants suggestions for
+    #[serde(default = "default_suggestion_threshold")]
+    pub threshold: u32, // Manual picks of the same browser before a rule is suggested
+}
+
+impl Default for Suggestions {
+    fn default() -> Self {
+        Self {
+            picks: Vec::new(),
+            ignored_origins: Vec::new(),
+            threshold: default_suggestion_threshold(),
+        }
+    }
+}
+
+/// A user-defined redirect wrapper. URLs matching `pattern` are replaced by
+/// the value of their `param` query parameter before rules are checked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Unwrapper {
+    pub pattern: String, // Glob pattern matching the wrapper URL
+    pub param: String,   // Query parameter holding the real destination
+}
+
+/// A URL rewrite applied before routing rules, e.g. `www.reddit.com` to `old.reddit.com`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RewriteRule {
+    pub id: String,
+    pub pattern: String,     // Regex matched against the URL
+    pub replacement: String, // Replacement text; `$1`, `${name}` refer to capture groups
+}
+
+/// Settings for stripping tracking parameters (`utm_*`, `fbclid`, ...) from URLs before opening.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackingSettings {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub params: Vec<String>, // Extra glob patterns for parameter names to strip
+    #[serde(default)]
+    pub exceptions: Vec<String>, // Glob patterns for hosts whose URLs are left untouched
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Default for TrackingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            params: Vec::new(),
+            exceptions: Vec::new(),
+        }
+    }
+}
+
+/// Settings for expanding short links (`bit.ly`, `t.co`, ...) by following their redirects.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShortLinkSettings {
+    #[serde(default)]
+    pub enabled: bool, // Off by default, as expanding a link contacts the shortener
+    #[serde(default)]
+    pub hosts: Vec<String>, // Extra glob patterns for shortener hosts, e.g. internal go-links
+}
+
+/// What happens when the picker is ignored: after `countdown_secs`, the URL
+/// opens in the browser of the rule it matches, or failing that the default browser.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FallbackSettings {
+    #[serde(default)]
+    pub browser_id: String, // Default browser (empty = none)
+    #[serde(default)]
+    pub countdown_secs: u32, // Seconds before opening automatically (0 = never)
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_suggestion_threshold() -> u32 {
+    3
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     pub browsers: Vec<Browser>,
     #[serde(default)]
     pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub suggestions: Suggestions,
+    #[serde(default)]
+    pub unwrappers: Vec<Unwrapper>,
+    #[serde(default)]
+    pub tracking: TrackingSettings,
+    #[serde(default)]
+    pub rewrites: Vec<RewriteRule>,
+    #[serde(default)]
+    pub short_links: ShortLinkSettings,
+    #[serde(default)]
+    pub schemes: Vec<String>, // Extra URL schemes to register for, beyond http and https
+    #[serde(default)]
+    pub apps: Vec<AppTarget>,
+    #[serde(default)]
+    pub fallback: FallbackSettings,
+    #[serde(default)]
+    pub groups: Vec<BrowserGroup>,
+    #[serde(skip)]
+    pub shadowed: Shadowed, // User entries hidden by system entries with the same id
+}
+
+/// The user's browsers and rules whose id is taken by an entry in the system config, with their
+/// position. They are left out while the system config has that id, but are still saved.
+#[derive(Debug, Clone, Default)]
+pub struct Shadowed {
+    browsers: Vec<(usize, Browser)>,
+    rules: Vec<(usize, Rule)>,
 }
 
 impl Config {
@@ -33,20 +196,80 @@ impl Config {
 
         if !config_path.exists() {
             // Create default config if it doesn't exist
-            let default_config = Self::create_default(app_handle)?;
+            let mut default_config = Self::create_default(app_handle)?;
             default_config.save(app_handle)?;
+            default_config.add_system_layer(&system_config_dir())?;
             return Ok(default_config);
         }
 
         let content = fs::read_to_string(&config_path)
             .map_err(|e| format!("Failed to read config file: {}", e))?;
 
-        let config: Config = serde_json::from_str(&content)
+        let mut config: Config = ConfigFormat::from_path(&config_path)
+            .unwrap_or(ConfigFormat::Json)
+            .parse(&content)
             .map_err(|e| format!("Failed to parse config file: {}", e))?;
 
+        config.add_system_layer(&system_config_dir())?;
         Ok(config)
     }
 
+    /// Layer the system config over this one. Its browsers and rules come first, so its rules take
+    /// priority, and are locked; they hide any here with the same id, which are set aside so
+    /// saving keeps them.
+    fn add_system_layer(&mut self, dir: &Path) -> Result<(), String> {
+        let Some(path) = config_format::find_config_file(dir) else {
+            return Ok(());
+        };
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read system config file: {}", e))?;
+        let system: SystemConfig = ConfigFormat::from_path(&path)
+            .unwrap_or(ConfigFormat::Json)
+            .parse(&content)
+            .map_err(|e| format!("Failed to parse system config file: {}", e))?;
+
+        self.shadowed.browsers = set_aside(&mut self.browsers, |b| {
+            system.browsers.iter().any(|s| s.id == b.id)
+        });
+        let mut browsers: Vec<Browser> = system
+            .browsers
+            .into_iter()
+            .map(|browser| Browser {
+                locked: true,
+                ..browser
+            })
+            .collect();
+        browsers.append(&mut self.browsers);
+        self.browsers = browsers;
+
+        self.shadowed.rules = set_aside(&mut self.rules, |r| {
+            system.rules.iter().any(|s| s.id == r.id)
+        });
+        let mut rules: Vec<Rule> = system
+            .rules
+            .into_iter()
+            .map(|rule| Rule {
+                locked: true,
+                ..rule
+            })
+            .collect();
+        rules.append(&mut self.rules);
+        self.rules = rules;
+
+        Ok(())
+    }
+
+    /// The config without the entries from the system config, as saved for the user, with the
+    /// entries they hid put back.
+    pub fn user_layer(&self) -> Config {
+        let mut config = self.clone();
+        config.browsers.retain(|b| !b.locked);
+        config.rules.retain(|r| !r.locked);
+        put_back(&mut config.browsers, &self.shadowed.browsers);
+        put_back(&mut config.rules, &self.shadowed.rules);
+        config
+    }
+
     /// Save config to the app data directory
     pub fn save(&self, app_handle: &tauri::AppHandle) -> Result<(), String> {
         let config_path = Self::get_config_path(app_handle)?;
@@ -57,7 +280,11 @@ impl Config {
                 .map_err(|e| format!("Failed to create config directory: {}", e))?;
         }
 
-        let content = serde_json::to_string_pretty(self)
+        // Comments in a hand-edited file are kept where the format allows
+        let existing = fs::read_to_string(&config_path).ok();
+        let content = ConfigFormat::from_path(&config_path)
+            .unwrap_or(ConfigFormat::Json)
+            .serialize(&self.user_layer(), existing.as_deref())
             .map_err(|e| format!("Failed to serialize config: {}", e))?;
 
         fs::write(&config_path, content)
@@ -66,19 +293,108 @@ impl Config {
         Ok(())
     }
 
-    /// Get the path to the config file
+    /// Get the path to the config file: `config.toml`, `config.yaml` or `config.json`, whichever
+    /// exists, or `config.json` if there is none yet
     pub fn get_config_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
         let app_data_dir = app_handle
             .path()
             .app_data_dir()
             .map_err(|e| format!("Failed to get app data directory: {}", e))?;
 
-        Ok(app_data_dir.join("config.json"))
+        Ok(config_format::find_config_file(&app_data_dir)
+            .unwrap_or_else(|| app_data_dir.join(ConfigFormat::Json.file_name())))
+    }
+
+    /// The files `load` reads: the user's config file, then the system config file if there is
+    /// one. Whatever they hold only changes when one of them does.
+    pub fn source_files(app_handle: &tauri::AppHandle) -> Result<Vec<PathBuf>, String> {
+        let mut files = vec![Self::get_config_path(app_handle)?];
+        files.extend(config_format::find_config_file(&system_config_dir()));
+        Ok(files)
+    }
+
+    /// Rewrite the config file in another format, returning its new path. Config files in other
+    /// formats are kept, with `.bak` added to their name so they aren't read instead.
+    pub fn convert(app_handle: &tauri::AppHandle, format: ConfigFormat) -> Result<PathBuf, String> {
+        let config = Self::load(app_handle)?;
+        let old_path = Self::get_config_path(app_handle)?;
+        let new_path = old_path.with_file_name(format.file_name());
+        if ConfigFormat::from_path(&old_path) == Some(format) {
+            return Ok(old_path);
+        }
+
+        let content = format.serialize(&config.user_layer(), None)?;
+        fs::write(&new_path, content).map_err(|e| format!("Failed to write config file: {}", e))?;
+
+        let dir = new_path.parent().unwrap_or(Path::new("."));
+        for path in config_format::config_files(dir) {
+            if path == new_path {
+                continue;
+            }
+            fs::rename(&path, backup_path(&path))
+                .map_err(|e| format!("Failed to rename '{}': {}", path.display(), e))?;
+        }
+
+        Ok(new_path)
+    }
+}
+
+/// The system config, where administrators set up browsers and rules that every user gets and
+/// can't change. Only its browsers and rules are read.
+#[derive(Debug, Default, Deserialize)]
+struct SystemConfig {
+    #[serde(default)]
+    browsers: Vec<Browser>,
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+/// A free name to back a file up to: `<file>.bak`, or `<file>.2.bak`, `<file>.3.bak`, ... if
+/// earlier backups exist.
+fn backup_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    (1..)
+        .map(|n| match n {
+            1 => path.with_file_name(format!("{}.bak", name)),
+            n => path.with_file_name(format!("{}.{}.bak", name, n)),
+        })
+        .find(|backup| !backup.exists())
+        .unwrap()
+}
+
+/// Removes the entries that are `taken`, returning them with their position.
+fn set_aside<T>(entries: &mut Vec<T>, taken: impl Fn(&T) -> bool) -> Vec<(usize, T)> {
+    let mut removed = Vec::new();
+    let mut kept = Vec::with_capacity(entries.len());
+    for (idx, entry) in std::mem::take(entries).into_iter().enumerate() {
+        if taken(&entry) {
+            removed.push((idx, entry));
+        } else {
+            kept.push(entry);
+        }
+    }
+    *entries = kept;
+    removed
+}
+
+/// Puts entries removed by `set_aside` back at their position, or at the end if
+/// the list has since become shorter.
+fn put_back<T: Clone>(entries: &mut Vec<T>, removed: &[(usize, T)]) {
+    for (idx, entry) in removed {
+        entries.insert((*idx).min(entries.len()), entry.clone());
+    }
+}
+
+fn system_config_dir() -> PathBuf {
+    if cfg!(target_os = "windows") {
+        PathBuf::from("C:\\ProgramData\\pick-browser")
+    } else {
+        PathBuf::from("/etc/pick-browser")
     }
 }
 
 /// Check whether a browser binary exists at the given path.
-fn browser_is_installed(path: &str) -> bool {
+pub fn browser_is_installed(path: &str) -> bool {
     // Absolute paths: check the file directly
     if Path::new(path).is_absolute() {
         return Path::new(path).exists();
@@ -92,6 +408,50 @@ fn browser_is_installed(path: &str) -> bool {
         .unwrap_or(false)
 }
 
+/// Well-known browsers and where they're installed on this platform.
+pub fn known_browser_paths() -> Vec<(&'static str, &'static str)> {
+    vec![
+        (
+            "Chrome",
+            if cfg!(target_os = "windows") {
+                "C:\\Program Files\\Google\\Chrome\\Application\\chrome.exe"
+            } else if cfg!(target_os = "macos") {
+                "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome"
+            } else {
+                "google-chrome"
+            },
+        ),
+        (
+            "Firefox",
+            if cfg!(target_os = "windows") {
+                "C:\\Program Files\\Mozilla Firefox\\firefox.exe"
+            } else if cfg!(target_os = "macos") {
+                "/Applications/Firefox.app/Contents/MacOS/firefox"
+            } else {
+                "firefox"
+            },
+        ),
+        (
+            "Edge",
+            if cfg!(target_os = "windows") {
+                "C:\\Program Files (x86)\\Microsoft\\Edge\\Application\\msedge.exe"
+            } else if cfg!(target_os = "macos") {
+                "/Applications/Microsoft Edge.app/Contents/MacOS/Microsoft Edge"
+            } else {
+                "microsoft-edge"
+            },
+        ),
+        (
+            "Safari",
+            if cfg!(target_os = "macos") {
+                "/Applications/Safari.app/Contents/MacOS/Safari"
+            } else {
+                "safari"
+            },
+        ),
+    ]
+}
+
 /// Known browsers and their bundled icon asset filenames.
 const KNOWN_BROWSER_ICONS: &[(&str, &str)] = &[
     ("chrome", "assets/chrome.png"),
@@ -122,86 +482,248 @@ pub fn copy_bundled_icon(
         .resolve(asset_path, BaseDirectory::Resource)
         .ok()?;
 
-    if !resource_path.exists() {
+    copy_icon_file(app_handle, &resource_path, browser_id)
+}
+
+/// Copies an icon file (e.g. one found through a `.desktop` file) to the user's icon directory,
+/// normalising it like an uploaded icon. Returns the file extension on success, or None if the
+/// file can't be copied.
+pub fn copy_icon_file(
+    app_handle: &tauri::AppHandle,
+    source: &Path,
+    browser_id: &str,
+) -> Option<String> {
+    if !source.exists() {
         return None;
     }
 
-    let ext = Path::new(asset_path).extension()?.to_str()?.to_string();
+    let format = IconFormat::from_extension(source.extension()?.to_str()?)?;
+    let bytes = fs::read(source).ok()?;
+    let prepared = icons::prepare(&bytes, format.mime_type()).ok()?;
 
     let icons_dir = app_handle.path().app_data_dir().ok()?.join("icons");
 
     fs::create_dir_all(&icons_dir).ok()?;
 
-    let dest = icons_dir.join(format!("{}.{}", browser_id, ext));
-    fs::copy(&resource_path, &dest).ok()?;
+    prepared.save(&icons_dir, browser_id).ok()
+}
 
-    Some(ext)
+/// Finds an icon for a browser automatically: the bundled icon for known browsers, otherwise
+/// (on Linux) the icon of its `.desktop` file. Returns the file extension of the copied icon.
+pub fn detect_browser_icon(
+    app_handle: &tauri::AppHandle,
+    name: &str,
+    path: &str,
+    browser_id: &str,
+) -> Option<String> {
+    get_known_browser_asset(name)
+        .and_then(|asset| copy_bundled_icon(app_handle, asset, browser_id))
+        .or_else(|| {
+            let icon = desktop_icons::find_icon(path)?;
+            copy_icon_file(app_handle, &icon, browser_id)
+        })
 }
 
 impl Config {
     /// Create the default config by detecting installed browsers and copying
-    /// bundled icons for known browsers.
+    /// their icons.
     fn create_default(app_handle: &tauri::AppHandle) -> Result<Self, String> {
-        let candidates: Vec<(&str, &str)> = vec![
-            (
-                "Chrome",
-                if cfg!(target_os = "windows") {
-                    "C:\\Program Files\\Google\\Chrome\\Application\\chrome.exe"
-                } else if cfg!(target_os = "macos") {
-                    "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome"
-                } else {
-                    "google-chrome"
-                },
-            ),
-            (
-                "Firefox",
-                if cfg!(target_os = "windows") {
-                    "C:\\Program Files\\Mozilla Firefox\\firefox.exe"
-                } else if cfg!(target_os = "macos") {
-                    "/Applications/Firefox.app/Contents/MacOS/firefox"
-                } else {
-                    "firefox"
-                },
-            ),
-            (
-                "Edge",
-                if cfg!(target_os = "windows") {
-                    "C:\\Program Files (x86)\\Microsoft\\Edge\\Application\\msedge.exe"
-                } else if cfg!(target_os = "macos") {
-                    "/Applications/Microsoft Edge.app/Contents/MacOS/Microsoft Edge"
-                } else {
-                    "microsoft-edge"
-                },
-            ),
-            (
-                "Safari",
-                if cfg!(target_os = "macos") {
-                    "/Applications/Safari.app/Contents/MacOS/Safari"
-                } else {
-                    "safari"
-                },
-            ),
-        ];
-
-        let browsers = candidates
+        let browsers = known_browser_paths()
             .into_iter()
             .filter(|(_, path)| browser_is_installed(path))
             .map(|(name, path)| {
                 let id = cuid2::create_id();
-                let icon = get_known_browser_asset(name)
-                    .and_then(|asset| copy_bundled_icon(app_handle, asset, &id));
+                let icon = detect_browser_icon(app_handle, name, path, &id);
                 Browser {
                     id,
                     name: name.to_string(),
                     path: path.to_string(),
                     icon,
+                    strip_tracking: true,
+                    schemes: Vec::new(),
+                    hidden: false,
+                    locked: false,
                 }
             })
             .collect();
 
         Ok(Config {
             browsers,
-            rules: Vec::new(),
+            ..Default::default()
         })
     }
 }
+
+/// Builders for the configs tests need, so each test only spells out what it cares about.
+#[cfg(test)]
+pub mod test_support {
+    use super::{AppTarget, Browser, Config, Rule};
+
+    /// A browser without an icon, launched by an executable named after its id.
+    pub fn browser(id: &str, name: &str) -> Browser {
+        Browser {
+            id: id.to_string(),
+            name: name.to_string(),
+            path: id.to_string(),
+            icon: None,
+            strip_tracking: true,
+            schemes: Vec::new(),
+            hidden: false,
+            locked: false,
+        }
+    }
+
+    /// A rule opening URLs matching `pattern` in a browser (empty = prompt to choose).
+    pub fn rule(id: &str, pattern: &str, browser_id: &str) -> Rule {
+        Rule {
+            id: id.to_string(),
+            pattern: pattern.to_string(),
+            browser_id: browser_id.to_string(),
+            strip_tracking: true,
+            app_id: String::new(),
+            group_id: String::new(),
+            locked: false,
+        }
+    }
+
+    /// An application target, launched by the system's handler for its URIs.
+    pub fn app(id: &str, pattern: &str, template: &str) -> AppTarget {
+        AppTarget {
+            id: id.to_string(),
+            name: id.to_string(),
+            pattern: pattern.to_string(),
+            template: template.to_string(),
+            command: String::new(),
+        }
+    }
+
+    /// A config with Firefox (`ff`) and Work (`work`), and no rules.
+    pub fn config() -> Config {
+        Config {
+            browsers: vec![browser("ff", "Firefox"), browser("work", "Work")],
+            ..Default::default()
+        }
+    }
+
+    /// A glob that fails to compile, as it's too big for the regex engine; other globs always do.
+    pub fn invalid_pattern() -> String {
+        "*a".repeat(10_000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_layer_comes_first_locked_and_is_not_saved() {
+        let dir = std::env::temp_dir().join(format!("pick-browser-system-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        fs::write(
+            &path,
+            r#"{
+                "browsers": [{"id": "managed", "name": "Managed", "path": "managed", "icon": null}],
+                "rules": [{"id": "sso", "pattern": "*://sso.example.com/*", "browser_id": "managed"}]
+            }"#,
+        )
+        .unwrap();
+
+        let mut config: Config = serde_json::from_str(
+            r#"{
+                "browsers": [
+                    {"id": "managed", "name": "Old", "path": "old", "icon": null},
+                    {"id": "own", "name": "Own", "path": "own", "icon": null, "locked": true}
+                ],
+                "rules": [{"id": "mine", "pattern": "*", "browser_id": "own"}]
+            }"#,
+        )
+        .unwrap();
+        config.add_system_layer(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The user's own entries can't lock themselves
+        let browsers: Vec<_> = config
+            .browsers
+            .iter()
+            .map(|b| (b.id.as_str(), b.name.as_str(), b.locked))
+            .collect();
+        assert_eq!(
+            browsers,
+            [("managed", "Managed", true), ("own", "Own", false)]
+        );
+        let rules: Vec<_> = config
+            .rules
+            .iter()
+            .map(|r| (r.id.as_str(), r.locked))
+            .collect();
+        assert_eq!(rules, [("sso", true), ("mine", false)]);
+
+        // The user's browser hidden by the managed one is still saved
+        let user = config.user_layer();
+        let names: Vec<_> = user.browsers.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["Old", "Own"]);
+        assert_eq!(user.rules.len(), 1);
+        let saved = serde_json::to_string(&user).unwrap();
+        assert!(!saved.contains("locked"));
+    }
+
+    #[test]
+    fn backups_dont_overwrite_earlier_ones() {
+        let dir = std::env::temp_dir().join(format!("pick-browser-backup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+
+        assert_eq!(backup_path(&path), dir.join("config.json.bak"));
+        fs::write(dir.join("config.json.bak"), b"").unwrap();
+        assert_eq!(backup_path(&path), dir.join("config.json.2.bak"));
+        fs::write(dir.join("config.json.2.bak"), b"").unwrap();
+        assert_eq!(backup_path(&path), dir.join("config.json.3.bak"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn user_entries_hidden_by_the_system_layer_are_still_saved() {
+        let dir = std::env::temp_dir().join(format!("pick-browser-shadow-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("config.json"),
+            r#"{
+                "browsers": [{"id": "b2", "name": "Managed", "path": "managed", "icon": null}],
+                "rules": [{"id": "r1", "pattern": "*://sso.example.com/*", "browser_id": "b2"}]
+            }"#,
+        )
+        .unwrap();
+
+        let mut config: Config = serde_json::from_str(
+            r#"{
+                "browsers": [
+                    {"id": "b1", "name": "First", "path": "first", "icon": null},
+                    {"id": "b2", "name": "Mine", "path": "mine", "icon": null},
+                    {"id": "b3", "name": "Third", "path": "third", "icon": null}
+                ],
+                "rules": [
+                    {"id": "r1", "pattern": "*://mine.example.com/*", "browser_id": "b1"},
+                    {"id": "r2", "pattern": "*", "browser_id": "b3"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        config.add_system_layer(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // While the system config has their ids, only its entries are used
+        let names: Vec<_> = config.browsers.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["Managed", "First", "Third"]);
+        let patterns: Vec<_> = config.rules.iter().map(|r| r.pattern.as_str()).collect();
+        assert_eq!(patterns, ["*://sso.example.com/*", "*"]);
+
+        // But the user's own are saved, where they were
+        let user = config.user_layer();
+        let names: Vec<_> = user.browsers.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["First", "Mine", "Third"]);
+        let patterns: Vec<_> = user.rules.iter().map(|r| r.pattern.as_str()).collect();
+        assert_eq!(patterns, ["*://mine.example.com/*", "*"]);
+    }
+}
@@ -0,0 +1,285 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, Table};
+
+/// File formats the config can be written in. JSON is the default; TOML and
+/// YAML are easier to edit by hand, and allow comments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+/// Config file names, in the order they're looked for.
+const FILE_NAMES: &[&str] = &["config.toml", "config.yaml", "config.yml", "config.json"];
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(ConfigFormat::Json),
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "config.json",
+            ConfigFormat::Toml => "config.toml",
+            ConfigFormat::Yaml => "config.yaml",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "JSON",
+            ConfigFormat::Toml => "TOML",
+            ConfigFormat::Yaml => "YAML",
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned>(self, content: &str) -> Result<T, String> {
+        let parsed = match self {
+            ConfigFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml_edit::de::from_str(content).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml_ng::from_str(content).map_err(|e| e.to_string()),
+        };
+        parsed.map_err(|e| format!("Invalid {}: {}", self.name(), e))
+    }
+
+    /// Serializes a value. For TOML, the comments and layout of `existing`, the
+    /// file's current content, are kept for everything that is still there;
+    /// comments in YAML and JSON files are lost.
+    pub fn serialize<T: Serialize>(
+        self,
+        value: &T,
+        existing: Option<&str>,
+    ) -> Result<String, String> {
+        let serialized = match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml_ng::to_string(value).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml_edit::ser::to_document(value)
+                .map_err(|e| e.to_string())
+                .map(|new| {
+                    let mut new = new.as_table().clone();
+                    expand_tables(&mut new);
+                    let mut document = existing
+                        .and_then(|content| content.parse::<DocumentMut>().ok())
+                        .unwrap_or_default();
+                    merge_table(document.as_table_mut(), new);
+                    renumber_tables(document.as_table_mut(), &mut 0);
+                    document.to_string()
+                }),
+        };
+        serialized.map_err(|e| format!("Failed to write {}: {}", self.name(), e))
+    }
+}
+
+/// The config files in a directory, in the order they're looked for.
+pub fn config_files(dir: &Path) -> Vec<PathBuf> {
+    FILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Finds the config file in a directory, whichever format it's in.
+pub fn find_config_file(dir: &Path) -> Option<PathBuf> {
+    config_files(dir).into_iter().next()
+}
+
+/// Turns the inline tables the serializer writes into `[table]` and `[[array]]`
+/// sections, which are easier to read and edit.
+fn expand_tables(table: &mut Table) {
+    for (_, item) in table.iter_mut() {
+        let expanded = match std::mem::take(item) {
+            Item::Value(value) if value.is_inline_table() => {
+                Item::Value(value).into_table().map(Item::Table)
+            }
+            Item::Value(value)
+                if value.as_array().is_some_and(|array| {
+                    !array.is_empty() && array.iter().all(|v| v.is_inline_table())
+                }) =>
+            {
+                Item::Value(value)
+                    .into_array_of_tables()
+                    .map(Item::ArrayOfTables)
+            }
+            other => Ok(other),
+        };
+        *item = expanded.unwrap_or_else(|item| item);
+        match item {
+            Item::Table(table) => expand_tables(table),
+            Item::ArrayOfTables(tables) => tables.iter_mut().for_each(expand_tables),
+            _ => {}
+        }
+    }
+}
+
+/// Copies `new` into `old`, keeping the comments and formatting of every key
+/// that is in both.
+fn merge_table(old: &mut Table, new: Table) {
+    let removed: Vec<String> = old
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !new.contains_key(key))
+        .collect();
+    for key in removed {
+        old.remove(&key);
+    }
+
+    for (key, item) in new {
+        match old.get_mut(&key) {
+            Some(existing) if same_kind(existing, &item) => merge_item(existing, item),
+            _ => {
+                // The key's formatting wouldn't fit a different kind of item
+                old.remove(&key);
+                old.insert(&key, item);
+            }
+        }
+    }
+}
+
+fn same_kind(a: &Item, b: &Item) -> bool {
+    matches!(
+        (a, b),
+        (Item::Table(_), Item::Table(_))
+            | (Item::ArrayOfTables(_), Item::ArrayOfTables(_))
+            | (Item::Value(_), Item::Value(_))
+    )
+}
+
+/// Orders sections as they are in the document's tables, as sections keep
+/// their original position otherwise, even when entries have been reordered.
+fn renumber_tables(table: &mut Table, next: &mut isize) {
+    for (_, item) in table.iter_mut() {
+        match item {
+            Item::Table(table) => {
+                table.set_position(Some(*next));
+                *next += 1;
+                renumber_tables(table, next);
+            }
+            Item::ArrayOfTables(tables) => {
+                for table in tables.iter_mut() {
+                    table.set_position(Some(*next));
+                    *next += 1;
+                    renumber_tables(table, next);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn merge_item(old: &mut Item, new: Item) {
+    match (old, new) {
+        (Item::Table(old), Item::Table(new)) => merge_table(old, new),
+        (Item::ArrayOfTables(old), Item::ArrayOfTables(new)) => {
+            // Entries are matched by id, as they may have been reordered, or by position
+            let mut previous: Vec<Option<Table>> =
+                std::mem::take(old).into_iter().map(Some).collect();
+            for (index, table) in new.into_iter().enumerate() {
+                let matched = match table.get("id").and_then(Item::as_str) {
+                    Some(id) => previous.iter().position(|t| {
+                        t.as_ref().and_then(|t| t.get("id")).and_then(Item::as_str) == Some(id)
+                    }),
+                    None => Some(index).filter(|&i| i < previous.len()),
+                };
+                match matched.and_then(|i| previous[i].take()) {
+                    Some(mut kept) => {
+                        merge_table(&mut kept, table);
+                        old.push(kept);
+                    }
+                    None => old.push(table),
+                }
+            }
+        }
+        (Item::Value(old), Item::Value(new)) => {
+            let decor = old.decor().clone();
+            *old = new;
+            *old.decor_mut() = decor;
+        }
+        (old, new) => *old = new, // Not reached, as merge_table checks the kinds match
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    const TOML: &str = r#"# Browsers, in the order they're shown
+[[browsers]]
+id = "ff"
+name = "Firefox"
+path = "firefox" # On the PATH
+
+[[browsers]]
+# Only for work
+id = "work"
+name = "Work"
+path = "chromium"
+
+[[rules]]
+id = "r1"
+pattern = "*://github.com/*"
+browser_id = "ff"
+
+[tracking]
+params = ["ref_*"] # Our own
+"#;
+
+    #[test]
+    fn all_formats_round_trip() {
+        let config: Config = ConfigFormat::Toml.parse(TOML).unwrap();
+        assert_eq!(config.browsers.len(), 2);
+        assert_eq!(config.tracking.params, ["ref_*"]);
+
+        for format in [ConfigFormat::Json, ConfigFormat::Toml, ConfigFormat::Yaml] {
+            let written = format.serialize(&config, None).unwrap();
+            let read: Config = format.parse(&written).unwrap();
+            assert_eq!(
+                serde_json::to_value(&read).unwrap(),
+                serde_json::to_value(&config).unwrap(),
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn toml_keeps_comments_when_saving() {
+        let mut config: Config = ConfigFormat::Toml.parse(TOML).unwrap();
+        config.browsers.swap(0, 1);
+        config.browsers[1].path = "/usr/bin/firefox".to_string();
+        config.rules.clear();
+
+        let written = ConfigFormat::Toml.serialize(&config, Some(TOML)).unwrap();
+
+        assert!(written.contains("# Only for work"));
+        assert!(written.contains("path = \"/usr/bin/firefox\" # On the PATH"));
+        assert!(written.contains("params = [\"ref_*\"] # Our own"));
+        assert!(!written.contains("github.com"));
+        let read: Config = ConfigFormat::Toml.parse(&written).unwrap();
+        let ids: Vec<_> = read.browsers.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(ids, ["work", "ff"]);
+    }
+
+    #[test]
+    fn formats_come_from_extensions() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.yml")),
+            Some(ConfigFormat::Yaml)
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.toml")),
+            Some(ConfigFormat::Toml)
+        );
+        assert_eq!(ConfigFormat::from_path(Path::new("config")), None);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::config::{Config, Rule};
+use serde::Serialize;
+
+/// A problem with a rule caused by an earlier rule.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleWarning {
+    pub rule_id: String,
+    pub kind: WarningKind,
+    pub other_rule_id: String, // The earlier rule causing the problem
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    Duplicate, // Same pattern as an earlier rule, so never used
+    Shadowed,  // Every URL it matches is matched by an earlier rule first
+    Overlap,   // Some URLs it matches go to an earlier rule with a different target
+}
+
+/// Finds rules that never or only partly take effect because of earlier rules.
+/// Rules are checked in order and the first match wins, so a later rule is
+/// shadowed if an earlier pattern covers it, and overlaps if the patterns share
+/// some URLs but send them to different targets. Earlier application rules are
+/// skipped, as they only match URLs their application can open, and so are
+/// earlier rules whose target no longer exists, as they never match.
+pub fn analyze(config: &Config) -> Vec<RuleWarning> {
+    let mut warnings = Vec::new();
+
+    for (idx, rule) in config.rules.iter().enumerate() {
+        let pattern = rule.pattern.to_lowercase();
+
+        let earlier_rules = config.rules[..idx]
+            .iter()
+            .filter(|r| r.app_id.is_empty() && resolves(config, r));
+        for earlier in earlier_rules {
+            let earlier_pattern = earlier.pattern.to_lowercase();
+
+            let (kind, message) = if earlier_pattern == pattern {
+                (
+                    WarningKind::Duplicate,
+                    format!("Same pattern as the earlier rule '{}'", earlier.pattern),
+                )
+            } else if covers(&earlier_pattern, &pattern) {
+                (
+                    WarningKind::Shadowed,
+                    format!(
+                        "Never used: every URL it matches is matched first by '{}'",
+                        earlier.pattern
+                    ),
+                )
+            } else if same_target(rule, earlier) || !intersects(&earlier_pattern, &pattern) {
+                continue;
+            } else {
+                (
+                    WarningKind::Overlap,
+                    format!(
+                        "Some URLs it matches are sent elsewhere by the earlier rule '{}'",
+                        earlier.pattern
+                    ),
+                )
+            };
+
+            warnings.push(RuleWarning {
+                rule_id: rule.id.clone(),
+                kind,
+                other_rule_id: earlier.id.clone(),
+                message,
+            });
+
+            // The first earlier rule that takes over is the one worth reporting
+            if !matches!(kind, WarningKind::Overlap) {
+                break;
+            }
+        }
+    }
+
+    warnings
+}
+
+fn same_target(a: &Rule, b: &Rule) -> bool {
+    a.browser_id == b.browser_id && a.app_id == b.app_id && a.group_id == b.group_id
+}
+
+/// Whether a rule's browser or group still exists, so it can match URLs.
+fn resolves(config: &Config, rule: &Rule) -> bool {
+    if !rule.group_id.is_empty() {
+        return config
+            .groups
+            .iter()
+            .any(|g| g.id == rule.group_id && !g.browser_ids.is_empty());
+    }
+    rule.browser_id.is_empty() || config.browsers.iter().any(|b| b.id == rule.browser_id)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Char(char),
+    Star, // `*`, matching anything, including `/`, as in glob_to_regex
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    pattern
+        .chars()
+        .map(|c| match c {
+            '*' => Token::Star,
+            c => Token::Char(c),
+        })
+        .collect()
+}
+
+/// Whether every URL matching glob `b` also matches glob `a`. A wildcard in
+/// `b` can stand for anything, so it can only be matched by a wildcard in `a`.
+fn covers(a: &str, b: &str) -> bool {
+    let a = tokenize(a);
+    let b = tokenize(b);
+
+    // covered[i][j]: whether a[i..] covers b[j..]
+    let mut covered = vec![vec![false; b.len() + 1]; a.len() + 1];
+    covered[a.len()][b.len()] = true;
+    for i in (0..a.len()).rev() {
+        for j in (0..=b.len()).rev() {
+            covered[i][j] = match a[i] {
+                Token::Char(c) => j < b.len() && b[j] == Token::Char(c) && covered[i + 1][j + 1],
+                Token::Star => covered[i + 1][j] || (j < b.len() && covered[i][j + 1]),
+            };
+        }
+    }
+    covered[0][0]
+}
+
+/// Whether some URL matches both glob `a` and glob `b`.
+fn intersects(a: &str, b: &str) -> bool {
+    let a = tokenize(a);
+    let b = tokenize(b);
+
+    // shared[i][j]: whether a[i..] and b[j..] match a common string
+    let mut shared = vec![vec![false; b.len() + 1]; a.len() + 1];
+    for i in (0..=a.len()).rev() {
+        for j in (0..=b.len()).rev() {
+            shared[i][j] = if i == a.len() && j == b.len() {
+                true
+            } else if i < a.len() && a[i] == Token::Star {
+                shared[i + 1][j] || (j < b.len() && shared[i][j + 1])
+            } else if j < b.len() && b[j] == Token::Star {
+                shared[i][j + 1] || (i < a.len() && shared[i + 1][j])
+            } else {
+                i < a.len() && j < b.len() && a[i] == b[j] && shared[i + 1][j + 1]
+            };
+        }
+    }
+    shared[0][0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_support::{self, rule};
+
+    #[test]
+    fn covers_exact_and_wider_patterns() {
+        assert!(covers("https://example.com/", "https://example.com/"));
+        assert!(covers(
+            "https://example.com/*",
+            "https://example.com/docs/*"
+        ));
+        assert!(covers("*://example.com/*", "https://example.com/a"));
+        assert!(!covers(
+            "https://example.com/docs/*",
+            "https://example.com/*"
+        ));
+        assert!(!covers("https://example.com/a", "https://example.com/*"));
+    }
+
+    #[test]
+    fn host_wildcards_can_match_slashes() {
+        // glob_to_regex turns every `*` into `.*`, even within the host
+        assert!(covers(
+            "https://*.example.com/*",
+            "https://a/b.example.com/*"
+        ));
+        assert!(covers(
+            "https://*.example.com/*",
+            "https://*.example.com/docs/*"
+        ));
+        assert!(intersects("https://*.example.com/", "https://a/*"));
+        assert!(!covers("https://*.example.com/*", "https://example.com/*"));
+    }
+
+    #[test]
+    fn partial_overlaps_intersect_without_covering() {
+        let a = "https://example.com/*";
+        let b = "*://example.com/docs/*";
+        assert!(intersects(a, b));
+        assert!(intersects(b, a));
+        assert!(!covers(a, b));
+        assert!(!covers(b, a));
+    }
+
+    #[test]
+    fn disjoint_patterns_dont_intersect() {
+        assert!(!intersects(
+            "https://example.com/*",
+            "https://example.org/*"
+        ));
+        assert!(!intersects("http://*", "https://*"));
+        assert!(!covers("https://example.com/*", "https://example.org/*"));
+    }
+
+    #[test]
+    fn rules_after_a_deleted_browser_arent_shadowed() {
+        let mut config = test_support::config();
+        config.rules = vec![
+            rule("r1", "*://example.com/*", "gone"),
+            rule("r2", "*://example.com/docs/*", "ff"),
+            rule("r3", "*://example.com/docs/a", "ff"),
+        ];
+        let warnings = analyze(&config);
+
+        assert!(matches!(
+            &warnings[..],
+            [RuleWarning { rule_id, kind: WarningKind::Shadowed, other_rule_id, .. }]
+                if rule_id == "r3" && other_rule_id == "r2"
+        ));
+    }
+}
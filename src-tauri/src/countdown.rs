@@ -0,0 +1,180 @@
+use crate::config::Config;
+use crate::{check_rule, launch_browser, rule_target, snooze, RuleCheck};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tauri::{Emitter, Manager};
+
+/// Tracks the running auto-open countdown. Starting or cancelling a countdown
+/// bumps the generation, which stops any countdown that is already running.
+#[derive(Default)]
+pub struct Countdown(AtomicU64);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CountdownTick {
+    pub browser_id: String, // Browser the URL will open in
+    pub remaining: u32,     // Seconds left
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CountdownFinished {
+    pub error: Option<String>, // Set if the browser failed to launch
+}
+
+/// Chooses the browser a URL falls back to if the picker is ignored: the
+/// browser of the rule it matches, otherwise the configured default browser.
+/// There is none if the matching rule prompts to choose, as the user must pick.
+/// A rule with an invalid pattern counts as no match.
+pub fn fallback_browser(config: &Config, url: &str) -> Option<String> {
+    let rule = config
+        .rules
+        .iter()
+        .find(|r| matches!(check_rule(config, r, url), Ok(RuleCheck::Applies)));
+    match rule {
+        Some(rule) if rule_target(rule).is_none() => None,
+        Some(rule) if !rule.browser_id.is_empty() => Some(rule.browser_id.clone()),
+        _ => Some(config.fallback.browser_id.clone())
+            .filter(|id| config.browsers.iter().any(|b| b.id == *id)),
+    }
+}
+
+/// Starts counting down to opening `url` in its fallback browser, emitting a
+/// `countdown-tick` event every second and `countdown-finished` once the
+/// browser has been launched. Replaces any countdown already running.
+/// Returns None, and starts nothing, if the countdown is disabled, there is
+/// no fallback browser, or a temporary override says to always prompt.
+pub fn start(
+    app_handle: &tauri::AppHandle,
+    url: String,
+    close: bool,
+) -> Result<Option<CountdownTick>, String> {
+    let config = Config::load(app_handle)?;
+    let generation = cancel(app_handle);
+
+    if snooze::active(app_handle).is_some_and(|o| o.browser_id.is_empty()) {
+        return Ok(None);
+    }
+    let seconds = config.fallback.countdown_secs;
+    let Some(browser_id) = fallback_browser(&config, &url).filter(|_| seconds > 0) else {
+        return Ok(None);
+    };
+
+    let tick = CountdownTick {
+        browser_id,
+        remaining: seconds,
+    };
+    let app_handle = app_handle.clone();
+    let browser_id = tick.browser_id.clone();
+    std::thread::spawn(move || run(app_handle, generation, url, browser_id, seconds, close));
+
+    Ok(Some(tick))
+}
+
+/// Stops the running countdown, if any. Returns the new generation.
+pub fn cancel(app_handle: &tauri::AppHandle) -> u64 {
+    app_handle
+        .state::<Countdown>()
+        .0
+        .fetch_add(1, Ordering::SeqCst)
+        + 1
+}
+
+fn run(
+    app_handle: tauri::AppHandle,
+    generation: u64,
+    url: String,
+    browser_id: String,
+    seconds: u32,
+    close: bool,
+) {
+    let is_current = || app_handle.state::<Countdown>().0.load(Ordering::SeqCst) == generation;
+
+    for remaining in (1..=seconds).rev() {
+        if !is_current() {
+            return;
+        }
+        let tick = CountdownTick {
+            browser_id: browser_id.clone(),
+            remaining,
+        };
+        let _ = app_handle.emit("countdown-tick", tick);
+        std::thread::sleep(Duration::from_secs(1));
+    }
+
+    if !is_current() {
+        return;
+    }
+
+    let result =
+        Config::load(&app_handle).and_then(|config| launch_browser(&config, &url, &browser_id));
+    if result.is_ok() && close {
+        app_handle.exit(0);
+        return;
+    }
+
+    let finished = CountdownFinished {
+        error: result.err(),
+    };
+    let _ = app_handle.emit("countdown-finished", finished);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_support::{self, app, invalid_pattern, rule};
+    use crate::config::Rule;
+
+    fn config() -> Config {
+        let mut config = test_support::config();
+        config.apps = vec![app("zoom", "zoom", "zoommtg:")];
+        config.rules = vec![
+            rule("bad", &invalid_pattern(), ""), // Would prompt, if it counted as a match
+            rule("gone", "*://gone.example.com/*", "deleted"),
+            rule("work", "*://*.work.com/*", "work"),
+            rule("ask", "*://ask.example.com/*", ""),
+            Rule {
+                app_id: "zoom".to_string(),
+                ..rule("zoom", "*://zoom.us/*", "")
+            },
+        ];
+        config.fallback.browser_id = "ff".to_string();
+        config.fallback.countdown_secs = 5;
+        config
+    }
+
+    #[test]
+    fn falls_back_to_the_matching_rules_browser() {
+        let config = config();
+        assert_eq!(
+            fallback_browser(&config, "https://wiki.work.com/").as_deref(),
+            Some("work")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_default_browser_without_a_browser_rule() {
+        let mut config = config();
+        assert!(check_rule(&config, &config.rules[0], "https://example.com/").is_err());
+        for url in [
+            "https://example.com/",
+            "https://gone.example.com/",
+            "https://zoom.us/j/1",
+        ] {
+            assert_eq!(
+                fallback_browser(&config, url).as_deref(),
+                Some("ff"),
+                "{}",
+                url
+            );
+        }
+
+        config.fallback.browser_id = "deleted".to_string();
+        assert_eq!(fallback_browser(&config, "https://example.com/"), None);
+    }
+
+    #[test]
+    fn rules_that_prompt_have_no_fallback() {
+        let config = config();
+        assert_eq!(fallback_browser(&config, "https://ask.example.com/"), None);
+    }
+}
@@ -0,0 +1,427 @@
+use crate::link_files::ini_value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Icon size to look for, in pixels. Icons are shown at 56px, so this leaves
+/// room for high-DPI screens.
+const PREFERRED_ICON_SIZE: u32 = 128;
+
+/// Icon file types that can be stored as browser icons, in order of preference.
+const ICON_EXTENSIONS: &[&str] = &["png", "svg"];
+
+/// Finds the icon of an installed browser on Linux, from the `Icon=` key of
+/// its `.desktop` file. Named icons are looked up in the active icon theme,
+/// the themes it inherits from, then hicolor, picking the size closest to
+/// PREFERRED_ICON_SIZE, and finally in the pixmaps directories.
+pub fn find_icon(browser_path: &str) -> Option<PathBuf> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+
+    let icon = find_desktop_entry_icon(browser_path)?;
+
+    // An absolute path is used as-is
+    let path = Path::new(&icon);
+    if path.is_absolute() {
+        return has_icon_extension(path).then(|| path.to_path_buf());
+    }
+
+    lookup_in_themes(&icon_base_dirs(), active_theme().as_deref(), &icon)
+        .or_else(|| lookup_in_pixmaps(&icon))
+}
+
+/// Finds `icon` in the active theme, the themes it inherits from, then hicolor.
+/// `bases` are the directories holding icon themes.
+fn lookup_in_themes(bases: &[PathBuf], active: Option<&str>, icon: &str) -> Option<PathBuf> {
+    let mut themes = Vec::new();
+    if let Some(active) = active {
+        push_theme_with_parents(bases, &mut themes, active);
+    }
+    push_theme_with_parents(bases, &mut themes, "hicolor");
+
+    themes
+        .iter()
+        .find_map(|theme| lookup_in_theme(bases, theme, icon))
+}
+
+/// The XDG data directories, most important first.
+fn data_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+
+    let mut dirs = Vec::new();
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => dirs.push(PathBuf::from(dir)),
+        _ => dirs.extend(home.as_ref().map(|h| h.join(".local/share"))),
+    }
+    let system_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(system_dirs.split(':').map(PathBuf::from));
+
+    // Flatpak and Snap apps export their desktop files and icons here
+    dirs.extend(home.map(|h| h.join(".local/share/flatpak/exports/share")));
+    dirs.push(PathBuf::from("/var/lib/flatpak/exports/share"));
+    dirs.push(PathBuf::from("/var/lib/snapd/desktop"));
+
+    dirs
+}
+
+/// Reads the `Icon=` key of the desktop entry that launches `browser_path`.
+/// Entries are matched on the executable in their `Exec=` key, or failing
+/// that on the desktop file being named after the executable.
+fn find_desktop_entry_icon(browser_path: &str) -> Option<String> {
+    let browser_name = Path::new(browser_path).file_name()?.to_str()?.to_string();
+    let browser_real = resolve_executable(browser_path);
+
+    let mut named_match = None;
+    for dir in data_dirs() {
+        let Ok(entries) = fs::read_dir(dir.join("applications")) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("desktop") {
+                continue;
+            }
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let Some(icon) = ini_value(&content, "Desktop Entry", "Icon") else {
+                continue;
+            };
+            if icon.is_empty() {
+                continue;
+            }
+
+            let exec = ini_value(&content, "Desktop Entry", "Exec").and_then(exec_program);
+            let exec_matches = exec.is_some_and(|exec| {
+                Path::new(&exec).file_name().and_then(|n| n.to_str()) == Some(&browser_name)
+                    || (browser_real.is_some() && resolve_executable(&exec) == browser_real)
+            });
+            if exec_matches {
+                return Some(icon.to_string());
+            }
+
+            if named_match.is_none()
+                && path.file_stem().and_then(|s| s.to_str()) == Some(&browser_name)
+            {
+                named_match = Some(icon.to_string());
+            }
+        }
+    }
+
+    named_match
+}
+
+/// Gets the program an `Exec=` line runs, skipping any `env VAR=value` prefix.
+fn exec_program(exec: &str) -> Option<String> {
+    exec.split_whitespace()
+        .find(|arg| *arg != "env" && !arg.contains('='))
+        .map(|arg| arg.trim_matches('"').to_string())
+}
+
+/// Resolves an executable name or path to its real location, following symlinks.
+fn resolve_executable(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.is_absolute() {
+        return fs::canonicalize(path).ok();
+    }
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(program))
+            .find(|p| p.is_file())
+            .and_then(|p| fs::canonicalize(p).ok())
+    })
+}
+
+/// The name of the user's icon theme, from the KDE or GTK settings.
+fn active_theme() -> Option<String> {
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"));
+
+    let from_file = |file: &str, section: &str, key: &str| {
+        let content = fs::read_to_string(config_home.join(file)).ok()?;
+        let value = ini_value(&content, section, key)?;
+        (!value.is_empty()).then(|| value.to_string())
+    };
+
+    from_file("kdeglobals", "Icons", "Theme")
+        .or_else(|| from_file("gtk-4.0/settings.ini", "Settings", "gtk-icon-theme-name"))
+        .or_else(|| from_file("gtk-3.0/settings.ini", "Settings", "gtk-icon-theme-name"))
+        .or_else(|| {
+            let output = Command::new("gsettings")
+                .args(["get", "org.gnome.desktop.interface", "icon-theme"])
+                .output()
+                .ok()?;
+            let theme = String::from_utf8(output.stdout).ok()?;
+            let theme = theme.trim().trim_matches('\'');
+            (output.status.success() && !theme.is_empty()).then(|| theme.to_string())
+        })
+}
+
+/// The directories icon themes can be in: `~/.icons` and `<data dir>/icons`.
+fn icon_base_dirs() -> Vec<PathBuf> {
+    let home_icons = std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".icons"));
+    home_icons
+        .into_iter()
+        .chain(data_dirs().into_iter().map(|d| d.join("icons")))
+        .collect()
+}
+
+/// The directories an icon theme's files can be in.
+fn theme_dirs(bases: &[PathBuf], theme: &str) -> Vec<PathBuf> {
+    bases
+        .iter()
+        .map(|d| d.join(theme))
+        .filter(|d| d.is_dir())
+        .collect()
+}
+
+fn read_theme_index(bases: &[PathBuf], theme: &str) -> Option<String> {
+    theme_dirs(bases, theme)
+        .iter()
+        .find_map(|dir| fs::read_to_string(dir.join("index.theme")).ok())
+}
+
+/// Adds a theme and, depth first, the themes it inherits from.
+fn push_theme_with_parents(bases: &[PathBuf], themes: &mut Vec<String>, theme: &str) {
+    if themes.iter().any(|t| t == theme) {
+        return;
+    }
+    themes.push(theme.to_string());
+
+    let Some(index) = read_theme_index(bases, theme) else {
+        return;
+    };
+    if let Some(parents) = ini_value(&index, "Icon Theme", "Inherits") {
+        for parent in parents.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            push_theme_with_parents(bases, themes, parent);
+        }
+    }
+}
+
+/// Finds the file for `icon` in one theme whose directory size is closest to
+/// PREFERRED_ICON_SIZE.
+fn lookup_in_theme(bases: &[PathBuf], theme: &str, icon: &str) -> Option<PathBuf> {
+    let index = read_theme_index(bases, theme)?;
+    let subdirs = ini_value(&index, "Icon Theme", "Directories")?;
+
+    let mut best: Option<(u32, PathBuf)> = None;
+    for subdir in subdirs.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let Some(distance) = size_distance(&index, subdir) else {
+            continue;
+        };
+        if best.as_ref().is_some_and(|(d, _)| *d <= distance) {
+            continue;
+        }
+        let found = theme_dirs(bases, theme).iter().find_map(|dir| {
+            ICON_EXTENSIONS
+                .iter()
+                .map(|ext| dir.join(subdir).join(format!("{}.{}", icon, ext)))
+                .find(|p| p.is_file())
+        });
+        if let Some(path) = found {
+            best = Some((distance, path));
+        }
+    }
+
+    best.map(|(_, path)| path)
+}
+
+/// How far a theme directory's icon size is from PREFERRED_ICON_SIZE, as
+/// described by its section in `index.theme`.
+fn size_distance(index: &str, subdir: &str) -> Option<u32> {
+    let number = |key: &str| ini_value(index, subdir, key).and_then(|v| v.parse::<u32>().ok());
+
+    let size = number("Size")?;
+    let scale = number("Scale").unwrap_or(1).max(1);
+    let wanted = PREFERRED_ICON_SIZE;
+
+    let distance = match ini_value(index, subdir, "Type").unwrap_or("Threshold") {
+        "Fixed" => (size * scale).abs_diff(wanted),
+        "Scalable" => {
+            let min = number("MinSize").unwrap_or(size) * scale;
+            let max = number("MaxSize").unwrap_or(size) * scale;
+            if wanted < min {
+                min - wanted
+            } else {
+                wanted.saturating_sub(max)
+            }
+        }
+        _ => {
+            let threshold = number("Threshold").unwrap_or(2);
+            let min = size.saturating_sub(threshold) * scale;
+            let max = (size + threshold) * scale;
+            if wanted < min {
+                min - wanted
+            } else {
+                wanted.saturating_sub(max)
+            }
+        }
+    };
+    Some(distance)
+}
+
+fn lookup_in_pixmaps(icon: &str) -> Option<PathBuf> {
+    data_dirs()
+        .iter()
+        .map(|d| d.join("pixmaps"))
+        .chain(std::iter::once(PathBuf::from("/usr/share/pixmaps")))
+        .flat_map(|dir| {
+            ICON_EXTENSIONS
+                .iter()
+                .map(move |ext| dir.join(format!("{}.{}", icon, ext)))
+        })
+        .find(|p| p.is_file())
+}
+
+fn has_icon_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| ICON_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A temporary directory of icon themes, removed when dropped.
+    struct Themes(PathBuf);
+
+    impl Themes {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "pick-browser-themes-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        /// Adds a theme with `index.theme` content, and the given icon files.
+        fn theme(&self, name: &str, index: &str, files: &[&str]) -> &Self {
+            let dir = self.0.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("index.theme"), index).unwrap();
+            for file in files {
+                let path = dir.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, b"").unwrap();
+            }
+            self
+        }
+
+        fn lookup(&self, active: Option<&str>, icon: &str) -> Option<String> {
+            let found = lookup_in_themes(std::slice::from_ref(&self.0), active, icon)?;
+            let relative = found.strip_prefix(&self.0).unwrap();
+            Some(relative.to_string_lossy().into_owned())
+        }
+    }
+
+    impl Drop for Themes {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const HICOLOR: &str = "[Icon Theme]
+Directories=48x48/apps,128x128/apps,scalable/apps
+
+[48x48/apps]
+Size=48
+Type=Fixed
+
+[128x128/apps]
+Size=128
+Type=Fixed
+
+[scalable/apps]
+Size=128
+MinSize=16
+MaxSize=512
+Type=Scalable
+";
+
+    fn theme_index(inherits: &str) -> String {
+        format!(
+            "[Icon Theme]
+Inherits={}
+Directories=64x64/apps
+
+[64x64/apps]
+Size=64
+Type=Fixed
+",
+            inherits
+        )
+    }
+
+    #[test]
+    fn active_theme_comes_before_hicolor() {
+        let themes = Themes::new("active");
+        themes
+            .theme("hicolor", HICOLOR, &["128x128/apps/firefox.png"])
+            .theme("Papirus", &theme_index(""), &["64x64/apps/firefox.png"]);
+
+        assert_eq!(
+            themes.lookup(Some("Papirus"), "firefox").as_deref(),
+            Some("Papirus/64x64/apps/firefox.png")
+        );
+        assert_eq!(
+            themes.lookup(None, "firefox").as_deref(),
+            Some("hicolor/128x128/apps/firefox.png")
+        );
+    }
+
+    #[test]
+    fn inherited_themes_come_before_hicolor() {
+        let themes = Themes::new("inherited");
+        themes
+            .theme("hicolor", HICOLOR, &["128x128/apps/firefox.png"])
+            .theme("Child", &theme_index("Parent"), &[])
+            .theme("Parent", &theme_index(""), &["64x64/apps/firefox.png"]);
+
+        assert_eq!(
+            themes.lookup(Some("Child"), "firefox").as_deref(),
+            Some("Parent/64x64/apps/firefox.png")
+        );
+        assert_eq!(themes.lookup(Some("Child"), "chromium"), None);
+    }
+
+    #[test]
+    fn closest_size_wins_within_a_theme() {
+        let themes = Themes::new("sizes");
+        themes.theme(
+            "hicolor",
+            HICOLOR,
+            &["48x48/apps/firefox.png", "scalable/apps/firefox.svg"],
+        );
+
+        assert_eq!(
+            themes.lookup(None, "firefox").as_deref(),
+            Some("hicolor/scalable/apps/firefox.svg")
+        );
+    }
+
+    #[test]
+    fn png_is_preferred_to_svg_of_the_same_size() {
+        let themes = Themes::new("formats");
+        themes.theme(
+            "hicolor",
+            HICOLOR,
+            &["128x128/apps/firefox.svg", "128x128/apps/firefox.png"],
+        );
+
+        assert_eq!(
+            themes.lookup(None, "firefox").as_deref(),
+            Some("hicolor/128x128/apps/firefox.png")
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use crate::config::ShortLinkSettings;
+use crate::glob_to_regex;
+use std::time::Duration;
+
+/// Maximum number of redirects to follow when expanding a short link.
+const MAX_REDIRECT_HOPS: usize = 5;
+
+/// Timeout for each request made while expanding a short link.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Built-in link shortener hosts, as glob patterns.
+const BUILTIN_SHORTENER_HOSTS: &[&str] = &[
+    "bit.ly",
+    "t.co",
+    "aka.ms",
+    "tinyurl.com",
+    "goo.gl",
+    "ow.ly",
+    "buff.ly",
+    "is.gd",
+    "lnkd.in",
+    "rebrand.ly",
+    "t.ly",
+    "cutt.ly",
+    "shorturl.at",
+    "youtu.be",
+    "amzn.to",
+    "fb.me",
+];
+
+/// Expands a short link by following its HTTP redirects, for as long as each
+/// hop stays on an allowed shortener host. Only shortener hosts are ever
+/// contacted: the final destination is never requested. Returns every URL
+/// along the way, one per redirect followed; empty if the URL isn't a short
+/// link, expansion is disabled, or the first request fails.
+pub fn expand_hops(url: &str, settings: &ShortLinkSettings) -> Vec<String> {
+    if !settings.enabled {
+        return Vec::new();
+    }
+
+    let hosts: Vec<regex::Regex> = BUILTIN_SHORTENER_HOSTS
+        .iter()
+        .copied()
+        .chain(settings.hosts.iter().map(String::as_str))
+        .filter_map(|pattern| glob_to_regex(pattern).ok())
+        .collect();
+
+    // Redirects are followed by hand so each hop can be checked against the allowlist.
+    // ureq keeps no cookies unless its `cookies` feature is enabled.
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .max_redirects(0)
+        .http_status_as_error(false)
+        .timeout_global(Some(REQUEST_TIMEOUT))
+        .build()
+        .into();
+
+    let mut hops: Vec<String> = Vec::new();
+    for _ in 0..MAX_REDIRECT_HOPS {
+        let current = hops.last().map(String::as_str).unwrap_or(url);
+        let Ok(parsed) = url::Url::parse(current) else {
+            break;
+        };
+        let host = parsed.host_str().unwrap_or_default();
+        if !hosts.iter().any(|re| re.is_match(host)) {
+            break;
+        }
+
+        match redirect_target(&agent, &parsed) {
+            Some(target) => hops.push(target),
+            None => break,
+        }
+    }
+
+    hops
+}
+
+/// Asks a shortener where a URL redirects to. Tries a HEAD request first, then
+/// falls back to GET for services that don't redirect HEAD requests. The
+/// response body is never read.
+fn redirect_target(agent: &ureq::Agent, url: &url::Url) -> Option<String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+
+    let location = |response: ureq::http::Response<ureq::Body>| -> Option<String> {
+        if !response.status().is_redirection() {
+            return None;
+        }
+        let location = response.headers().get("location")?.to_str().ok()?;
+        // Location may be relative to the URL that was requested
+        Some(url.join(location).ok()?.to_string())
+    };
+
+    agent
+        .head(url.as_str())
+        .call()
+        .ok()
+        .and_then(location)
+        .or_else(|| agent.get(url.as_str()).call().ok().and_then(location))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// A local stand-in for a link shortener. Each path redirects to a location
+    /// (relative to the server, unless absolute), optionally only for GET
+    /// requests; anything else is 404. Returns its address and the requests
+    /// made, as "METHOD /path".
+    fn serve(
+        redirects: Vec<(&'static str, &'static str, bool)>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let redirects: HashMap<_, _> = redirects
+            .into_iter()
+            .map(|(path, location, get_only)| (path, (location, get_only)))
+            .collect();
+
+        let log = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                // Skip the headers
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                    line.clear();
+                }
+
+                let mut parts = request_line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_string();
+                let path = parts.next().unwrap_or("/").to_string();
+                log.lock().unwrap().push(format!("{} {}", method, path));
+
+                let (status, location) = match redirects.get(path.as_str()) {
+                    Some((location, get_only)) if !get_only || method == "GET" => {
+                        ("301 Moved Permanently", *location)
+                    }
+                    Some(_) => ("200 OK", ""),
+                    None => ("404 Not Found", ""),
+                };
+                let head = format!(
+                    "HTTP/1.1 {}\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status, location
+                );
+                let _ = stream.write_all(head.as_bytes());
+            }
+        });
+
+        (address, requests)
+    }
+
+    /// Settings that treat the local server as a shortener.
+    fn settings() -> ShortLinkSettings {
+        ShortLinkSettings {
+            enabled: true,
+            hosts: vec!["127.0.0.1".to_string()],
+        }
+    }
+
+    fn requests(log: &Arc<Mutex<Vec<String>>>) -> Vec<String> {
+        log.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn follows_redirects_while_on_a_shortener() {
+        let (address, log) = serve(vec![
+            ("/a", "/b", false),
+            ("/b", "http://localhost:1/destination?x=1", false),
+        ]);
+
+        let hops = expand_hops(&format!("{}/a", address), &settings());
+
+        assert_eq!(
+            hops,
+            [
+                format!("{}/b", address),
+                "http://localhost:1/destination?x=1".to_string()
+            ]
+        );
+        // The destination isn't a shortener, so it's never requested
+        assert_eq!(requests(&log), ["HEAD /a", "HEAD /b"]);
+    }
+
+    #[test]
+    fn falls_back_to_get_when_head_doesnt_redirect() {
+        let (address, log) = serve(vec![("/a", "http://localhost:1/", true)]);
+
+        let hops = expand_hops(&format!("{}/a", address), &settings());
+
+        assert_eq!(hops, ["http://localhost:1/"]);
+        assert_eq!(requests(&log), ["HEAD /a", "GET /a"]);
+    }
+
+    #[test]
+    fn stops_after_the_hop_limit() {
+        let (address, log) = serve(vec![("/loop", "/loop", false)]);
+
+        let hops = expand_hops(&format!("{}/loop", address), &settings());
+
+        assert_eq!(hops.len(), MAX_REDIRECT_HOPS);
+        assert_eq!(requests(&log).len(), MAX_REDIRECT_HOPS);
+    }
+
+    #[test]
+    fn leaves_other_links_alone() {
+        let (address, log) = serve(vec![("/a", "/b", false)]);
+
+        // Not a redirect
+        assert!(expand_hops(&format!("{}/missing", address), &settings()).is_empty());
+        // Expansion is off
+        let disabled = ShortLinkSettings {
+            enabled: false,
+            ..settings()
+        };
+        assert!(expand_hops(&format!("{}/a", address), &disabled).is_empty());
+        // Not a shortener
+        assert!(expand_hops(
+            &format!("{}/a", address),
+            &ShortLinkSettings {
+                enabled: true,
+                hosts: Vec::new(),
+            }
+        )
+        .is_empty());
+
+        assert_eq!(requests(&log), ["HEAD /missing", "GET /missing"]);
+    }
+}
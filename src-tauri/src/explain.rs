@@ -0,0 +1,312 @@
+use crate::config::{Config, Rule};
+use crate::pipeline::{self, PipelineResult};
+use crate::snooze::TemporaryOverride;
+use crate::{check_rule, glob_to_regex, rule_target, RuleCheck, RuleTarget};
+use serde::Serialize;
+
+/// Everything that decides where a URL opens, in the order it happens.
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+    pub pipeline: PipelineResult, // Unwrapping, expansion and rewrites applied before the rules
+    pub override_active: Option<TemporaryOverride>, // Override used instead of the user's rules
+    pub rules: Vec<RuleStep>,     // Every rule, in the order they are checked
+    pub winner: Option<String>,   // ID of the rule that decided, if any
+    pub target: Option<RuleTarget>, // Where the URL opens (None = prompt to choose)
+    pub summary: String,
+}
+
+/// How one rule was evaluated.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleStep {
+    pub rule_id: String,
+    pub pattern: String,
+    pub outcome: Outcome,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Matched,      // This rule decided where the URL opens
+    NotMatched,   // The pattern doesn't match the URL
+    Skipped,      // The pattern matches, but the target can't open the URL
+    Invalid,      // The pattern doesn't parse, so the rule is ignored
+    NotConsulted, // An override or an earlier rule had already decided
+}
+
+/// Explains where `url` opens, mirroring `check_rules`: the URL is run through
+/// the pipeline and rules from the system config are checked first, then an
+/// active override takes precedence over the user's own rules, otherwise the
+/// first rule that applies decides. Rules whose pattern doesn't parse are
+/// marked as invalid and passed over.
+pub fn explain(config: &Config, url: &str, active: Option<TemporaryOverride>) -> Explanation {
+    let pipeline = pipeline::run(url, config);
+
+    // Overrides that point to a deleted browser are ignored, as in check_rules
+    let active = active.filter(|o| {
+        o.browser_id.is_empty() || config.browsers.iter().any(|b| b.id == o.browser_id)
+    });
+
+    let (locked, unlocked): (Vec<&Rule>, Vec<&Rule>) = config.rules.iter().partition(|r| r.locked);
+    let mut rules = Vec::with_capacity(config.rules.len());
+    let mut winner: Option<&Rule> = None;
+    for rule in locked {
+        rules.push(check(config, rule, &pipeline, &mut winner));
+    }
+    let active = active.filter(|_| winner.is_none());
+    for rule in unlocked {
+        if active.is_some() {
+            rules.push(not_consulted(rule, "A temporary override is active"));
+        } else {
+            rules.push(check(config, rule, &pipeline, &mut winner));
+        }
+    }
+
+    let (target, summary) = match (winner, &active) {
+        (Some(rule), _) => (
+            rule_target(rule),
+            format!("Rule '{}': {}", rule.pattern, describe_target(config, rule)),
+        ),
+        (None, Some(active)) if active.browser_id.is_empty() => (
+            None,
+            "A temporary override is active: always prompting to choose".to_string(),
+        ),
+        (None, Some(active)) => (
+            Some(RuleTarget::Browser(active.browser_id.clone())),
+            format!(
+                "A temporary override is active: opening everything in {}",
+                browser_name(config, &active.browser_id)
+            ),
+        ),
+        (None, None) => (None, "No rule matched, so the picker is shown".to_string()),
+    };
+    Explanation {
+        pipeline,
+        override_active: active,
+        rules,
+        winner: winner.map(|r| r.id.clone()),
+        target,
+        summary,
+    }
+}
+
+/// Checks one rule, unless an earlier one has already decided where the URL opens.
+fn check<'a>(
+    config: &Config,
+    rule: &'a Rule,
+    pipeline: &PipelineResult,
+    winner: &mut Option<&'a Rule>,
+) -> RuleStep {
+    if let Some(winner) = winner {
+        let reason = if rule_target(winner).is_none() {
+            format!("The earlier rule '{}' prompts to choose", winner.pattern)
+        } else {
+            format!("The earlier rule '{}' already matched", winner.pattern)
+        };
+        return not_consulted(rule, &reason);
+    }
+
+    let (outcome, reason) = match check_rule(config, rule, &pipeline.url) {
+        Ok(RuleCheck::Applies) => {
+            *winner = Some(rule);
+            (Outcome::Matched, describe_target(config, rule))
+        }
+        Ok(RuleCheck::Unusable(reason)) => (Outcome::Skipped, reason),
+        Ok(RuleCheck::NoMatch) => (Outcome::NotMatched, no_match_reason(rule, pipeline)),
+        Err(e) => (Outcome::Invalid, e),
+    };
+    RuleStep {
+        rule_id: rule.id.clone(),
+        pattern: rule.pattern.clone(),
+        outcome,
+        reason,
+    }
+}
+
+fn not_consulted(rule: &Rule, reason: &str) -> RuleStep {
+    RuleStep {
+        rule_id: rule.id.clone(),
+        pattern: rule.pattern.clone(),
+        outcome: Outcome::NotConsulted,
+        reason: reason.to_string(),
+    }
+}
+
+fn browser_name(config: &Config, id: &str) -> String {
+    config
+        .browsers
+        .iter()
+        .find(|b| b.id == id)
+        .map(|b| b.name.clone())
+        .unwrap_or_else(|| id.to_string())
+}
+
+fn describe_target(config: &Config, rule: &Rule) -> String {
+    match rule_target(rule) {
+        Some(RuleTarget::Browser(id)) => format!("Opens in {}", browser_name(config, &id)),
+        Some(RuleTarget::App(id)) => {
+            let name = config
+                .apps
+                .iter()
+                .find(|a| a.id == id)
+                .map(|a| a.name.as_str());
+            format!("Opens in the {} application", name.unwrap_or(&id))
+        }
+        Some(RuleTarget::Group(id)) => {
+            let name = config
+                .groups
+                .iter()
+                .find(|g| g.id == id)
+                .map(|g| g.name.as_str());
+            format!("Opens in every browser in {}", name.unwrap_or(&id))
+        }
+        None => "Prompts to choose, which stops any later rules being checked".to_string(),
+    }
+}
+
+/// Explains a pattern not matching, pointing out when only the pipeline's
+/// changes to the URL stopped it from matching.
+fn no_match_reason(rule: &Rule, pipeline: &PipelineResult) -> String {
+    let matched_original = pipeline.url != pipeline.original
+        && glob_to_regex(&rule.pattern).is_ok_and(|re| re.is_match(&pipeline.original));
+    if matched_original {
+        "Doesn't match the URL after unwrapping and rewriting, but would match the original link"
+            .to_string()
+    } else {
+        "Pattern doesn't match the URL".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_support::{browser, invalid_pattern, rule};
+
+    fn config() -> Config {
+        Config {
+            browsers: vec![browser("ff", "Firefox"), browser("sso", "SSO")],
+            rules: vec![
+                Rule {
+                    locked: true,
+                    ..rule("locked", "*://login.example.com/*", "sso")
+                },
+                rule("docs", "*://docs.example.com/*", "ff"),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn snooze(browser_id: &str) -> Option<TemporaryOverride> {
+        Some(TemporaryOverride {
+            browser_id: browser_id.to_string(),
+            expires_at: u64::MAX,
+        })
+    }
+
+    fn outcomes(explanation: &Explanation) -> Vec<(&str, Outcome)> {
+        explanation
+            .rules
+            .iter()
+            .map(|step| (step.rule_id.as_str(), step.outcome))
+            .collect()
+    }
+
+    #[test]
+    fn overrides_cant_skip_locked_rules() {
+        let config = config();
+
+        let explanation = explain(&config, "https://login.example.com/", snooze("ff"));
+        assert_eq!(explanation.winner.as_deref(), Some("locked"));
+        assert!(explanation.override_active.is_none());
+        assert!(matches!(&explanation.target, Some(RuleTarget::Browser(id)) if id == "sso"));
+
+        let explanation = explain(&config, "https://docs.example.com/", snooze(""));
+        assert_eq!(explanation.winner, None);
+        assert!(explanation.override_active.is_some());
+        assert!(explanation.target.is_none());
+        assert!(matches!(
+            outcomes(&explanation)[..],
+            [
+                ("locked", Outcome::NotMatched),
+                ("docs", Outcome::NotConsulted)
+            ]
+        ));
+    }
+
+    #[test]
+    fn the_first_matching_rule_decides() {
+        let mut config = config();
+        config.rules.push(rule("all", "*", "sso"));
+
+        let explanation = explain(&config, "https://docs.example.com/a", None);
+        assert_eq!(explanation.winner.as_deref(), Some("docs"));
+        assert!(matches!(&explanation.target, Some(RuleTarget::Browser(id)) if id == "ff"));
+        assert_eq!(
+            explanation.summary,
+            "Rule '*://docs.example.com/*': Opens in Firefox"
+        );
+        assert!(matches!(
+            outcomes(&explanation)[..],
+            [
+                ("locked", Outcome::NotMatched),
+                ("docs", Outcome::Matched),
+                ("all", Outcome::NotConsulted)
+            ]
+        ));
+    }
+
+    #[test]
+    fn rules_that_cant_open_the_url_are_skipped() {
+        let mut config = config();
+        config.rules[1].browser_id = "gone".to_string();
+        config.rules.push(Rule {
+            group_id: "gone".to_string(),
+            ..rule("group", "*://docs.example.com/*", "")
+        });
+
+        let explanation = explain(&config, "https://docs.example.com/a", None);
+        assert_eq!(explanation.winner, None);
+        assert!(explanation.target.is_none());
+        assert!(matches!(
+            outcomes(&explanation)[..],
+            [
+                ("locked", Outcome::NotMatched),
+                ("docs", Outcome::Skipped),
+                ("group", Outcome::Skipped)
+            ]
+        ));
+    }
+
+    #[test]
+    fn overrides_open_everything_in_their_browser() {
+        let config = config();
+
+        let explanation = explain(&config, "https://docs.example.com/", snooze("sso"));
+        assert_eq!(explanation.winner, None);
+        assert!(explanation.override_active.is_some());
+        assert!(matches!(&explanation.target, Some(RuleTarget::Browser(id)) if id == "sso"));
+
+        // Overrides pointing to a deleted browser are ignored
+        let explanation = explain(&config, "https://docs.example.com/", snooze("gone"));
+        assert_eq!(explanation.winner.as_deref(), Some("docs"));
+        assert!(explanation.override_active.is_none());
+    }
+
+    #[test]
+    fn invalid_patterns_are_passed_over() {
+        let mut config = config();
+        config.rules[1].pattern = invalid_pattern();
+        config.rules.push(rule("all", "*", "sso"));
+
+        let explanation = explain(&config, "https://docs.example.com/", None);
+        assert_eq!(explanation.winner.as_deref(), Some("all"));
+        assert!(matches!(
+            outcomes(&explanation)[..],
+            [
+                ("locked", Outcome::NotMatched),
+                ("docs", Outcome::Invalid),
+                ("all", Outcome::Matched)
+            ]
+        ));
+    }
+}
@@ -0,0 +1,489 @@
+use crate::icons::{self, IconFormat};
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Timeout for each request made while fetching a favicon.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum number of redirects to follow for each request.
+const MAX_REDIRECTS: u32 = 5;
+
+/// How much of a site's home page is read when looking for `<link rel="icon">`
+/// tags, which belong in `<head>`, near the start.
+const MAX_PAGE_BYTES: u64 = 512 * 1024;
+
+/// Largest favicon file that is downloaded.
+const MAX_FAVICON_BYTES: u64 = 1024 * 1024;
+
+/// Most icons listed by a page that are tried before falling back to `/favicon.ico`.
+const MAX_CANDIDATES: usize = 4;
+
+/// Pixel size favicons are stored at; they are shown at 16px.
+const FAVICON_SIZE: u32 = 32;
+
+/// How long a fetched favicon is kept before it is fetched again.
+const CACHE_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How long to wait before trying again for a site that has no favicon.
+const MISSING_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A site's favicon, normalised like a browser icon.
+pub struct Favicon {
+    pub format: IconFormat,
+    pub data: Vec<u8>,
+}
+
+/// Gets the favicon of the site `url` is on (a URL or just a host name), from
+/// the cache in `cache_dir` if it hasn't expired, otherwise by fetching the
+/// icons the home page links to, then `/favicon.ico`. Requests carry no
+/// cookies, time out after REQUEST_TIMEOUT and stop reading at a size limit.
+/// Sites that answer without a usable favicon are remembered too, so they
+/// aren't asked again for a while. Returns None if there is no favicon, and an
+/// error, which isn't cached, if the site couldn't be reached.
+pub fn get_favicon(cache_dir: &Path, url: &str) -> Result<Option<Favicon>, String> {
+    let site = site_url(url)?;
+    let key = cache_key(&site);
+
+    if let Some(cached) = read_cached(cache_dir, &key) {
+        return Ok(cached);
+    }
+
+    let favicon =
+        fetch(&site).map_err(|e| format!("Failed to fetch the favicon of {}: {}", site, e))?;
+    write_cached(cache_dir, &key, favicon.as_ref())?;
+    Ok(favicon)
+}
+
+/// The home page of the site a URL or host name belongs to.
+fn site_url(url: &str) -> Result<url::Url, String> {
+    // A bare host name (with an optional port) is taken to be an HTTPS site
+    let is_host = url
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'));
+    let parsed = if is_host && !url.is_empty() {
+        url::Url::parse(&format!("https://{}", url))
+    } else {
+        url::Url::parse(url)
+    }
+    .map_err(|e| format!("Invalid URL '{}': {}", url, e))?;
+
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(format!(
+            "Favicons can only be fetched for websites, not '{}'",
+            url
+        ));
+    }
+    parsed
+        .join("/")
+        .map_err(|e| format!("Invalid URL '{}': {}", url, e))
+}
+
+/// Favicons are cached per host (and port, if it isn't the default), in a file
+/// named after it. Anything that isn't safe in a file name is replaced.
+fn cache_key(site: &url::Url) -> String {
+    let host = site.host_str().unwrap_or_default();
+    let key = match site.port() {
+        Some(port) => format!("{}_{}", host, port),
+        None => host.to_string(),
+    };
+    key.chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Reads a cached favicon. Returns None if nothing usable is cached, and
+/// Some(None) if the site is known to have no favicon.
+fn read_cached(cache_dir: &Path, key: &str) -> Option<Option<Favicon>> {
+    let fresh = |path: &Path, expiry: Duration| {
+        std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age < expiry)
+    };
+
+    if fresh(&cache_dir.join(format!("{}.none", key)), MISSING_EXPIRY) {
+        return Some(None);
+    }
+    [IconFormat::Png, IconFormat::Avif]
+        .into_iter()
+        .find_map(|format| {
+            let path = cache_dir.join(format!("{}.{}", key, format.extension()));
+            if !fresh(&path, CACHE_EXPIRY) {
+                return None;
+            }
+            let data = std::fs::read(path).ok()?;
+            Some(Some(Favicon { format, data }))
+        })
+}
+
+/// Replaces whatever is cached for a site, writing an empty `.none` file if it has no favicon.
+fn write_cached(cache_dir: &Path, key: &str, favicon: Option<&Favicon>) -> Result<(), String> {
+    std::fs::create_dir_all(cache_dir)
+        .map_err(|e| format!("Failed to create favicon cache directory: {}", e))?;
+
+    for ext in ["none", "png", "avif"] {
+        let path = cache_dir.join(format!("{}.{}", key, ext));
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("Failed to remove cached favicon: {}", e))
+            }
+            _ => {}
+        }
+    }
+
+    let (ext, data) = match favicon {
+        Some(favicon) => (favicon.format.extension(), favicon.data.as_slice()),
+        None => ("none", &[][..]),
+    };
+    std::fs::write(cache_dir.join(format!("{}.{}", key, ext)), data)
+        .map_err(|e| format!("Failed to cache favicon: {}", e))
+}
+
+/// Fetches a site's favicon, trying the icons its home page links to before `/favicon.ico`. Fails
+/// if none was found and a request couldn't reach its server, as there may well be one.
+fn fetch(site: &url::Url) -> Result<Option<Favicon>, ureq::Error> {
+    // ureq keeps no cookies unless its `cookies` feature is enabled.
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .max_redirects(MAX_REDIRECTS)
+        .timeout_global(Some(REQUEST_TIMEOUT))
+        .build()
+        .into();
+
+    let mut candidates = home_page_icons(&agent, site)?;
+    candidates.truncate(MAX_CANDIDATES);
+    candidates.extend(site.join("/favicon.ico").ok());
+    candidates.dedup();
+
+    let mut failed = None;
+    for url in &candidates {
+        match download_icon(&agent, url) {
+            Ok(Some(favicon)) => return Ok(Some(favicon)),
+            Ok(None) => {}
+            Err(e) => failed = failed.or(Some(e)),
+        }
+    }
+    failed.map_or(Ok(None), Err)
+}
+
+/// Whether a request failed because its server couldn't be reached (being
+/// offline, a DNS failure, a timeout), rather than because of how it answered.
+fn is_unreachable(error: &ureq::Error) -> bool {
+    matches!(
+        error,
+        ureq::Error::Io(_)
+            | ureq::Error::Timeout(_)
+            | ureq::Error::HostNotFound
+            | ureq::Error::ConnectionFailed
+            | ureq::Error::BodyStalled
+    )
+}
+
+/// Reads the start of a site's home page for the icons it links to, best first. Fails only if the
+/// site couldn't be reached.
+fn home_page_icons(agent: &ureq::Agent, site: &url::Url) -> Result<Vec<url::Url>, ureq::Error> {
+    use ureq::ResponseExt;
+
+    let mut response = match agent.get(site.as_str()).call() {
+        Ok(response) => response,
+        Err(e) if is_unreachable(&e) => return Err(e),
+        Err(_) => return Ok(Vec::new()),
+    };
+    // Links are relative to where any redirects ended up
+    let base = url::Url::parse(&response.get_uri().to_string()).unwrap_or_else(|_| site.clone());
+
+    let mut html = Vec::new();
+    if let Err(e) = response
+        .body_mut()
+        .as_reader()
+        .take(MAX_PAGE_BYTES)
+        .read_to_end(&mut html)
+    {
+        return Err(ureq::Error::Io(e));
+    }
+
+    Ok(icon_links(&String::from_utf8_lossy(&html))
+        .into_iter()
+        .filter_map(|href| base.join(&href).ok())
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .collect())
+}
+
+/// Finds the `href` of every `<link>` tag for an icon, ordered by the size they
+/// declare, largest first (icons are downscaled, so bigger is better), with
+/// scalable icons first of all.
+fn icon_links(html: &str) -> Vec<String> {
+    let link_re = regex::Regex::new(r"(?is)<link\b[^>]*>").unwrap();
+    let attr_re =
+        regex::Regex::new(r#"(?is)([a-z-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap();
+
+    let mut links: Vec<(u32, String)> = Vec::new();
+    for tag in link_re.find_iter(html) {
+        let mut rel = String::new();
+        let mut href = None;
+        let mut sizes = String::new();
+        for attr in attr_re.captures_iter(tag.as_str()) {
+            let value = attr
+                .get(2)
+                .or_else(|| attr.get(3))
+                .or_else(|| attr.get(4))
+                .map(|v| v.as_str().trim())
+                .unwrap_or_default();
+            match attr[1].to_lowercase().as_str() {
+                "rel" => rel = value.to_lowercase(),
+                "href" => href = Some(value.to_string()),
+                "sizes" => sizes = value.to_lowercase(),
+                _ => {}
+            }
+        }
+
+        let is_icon = rel.split_whitespace().any(|r| {
+            matches!(
+                r,
+                "icon" | "apple-touch-icon" | "apple-touch-icon-precomposed"
+            )
+        });
+        let Some(href) = href.filter(|h| is_icon && !h.is_empty()) else {
+            continue;
+        };
+
+        // e.g. sizes="16x16 32x32" or sizes="any"
+        let size = sizes
+            .split_whitespace()
+            .map(|s| match s {
+                "any" => u32::MAX,
+                s => s
+                    .split('x')
+                    .next()
+                    .and_then(|w| w.parse().ok())
+                    .unwrap_or(0),
+            })
+            .max()
+            .unwrap_or(0);
+        links.push((size, href));
+    }
+
+    // A stable sort, so icons without sizes keep the page's order
+    links.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+    links.into_iter().map(|(_, href)| href).collect()
+}
+
+/// Downloads an icon and normalises it, or returns None if it isn't a usable image. Fails only if
+/// its server couldn't be reached.
+fn download_icon(agent: &ureq::Agent, url: &url::Url) -> Result<Option<Favicon>, ureq::Error> {
+    let bytes = agent.get(url.as_str()).call().and_then(|mut response| {
+        response
+            .body_mut()
+            .with_config()
+            .limit(MAX_FAVICON_BYTES)
+            .read_to_vec()
+    });
+    let bytes = match bytes {
+        Ok(bytes) => bytes,
+        Err(e) if is_unreachable(&e) => return Err(e),
+        Err(_) => return Ok(None),
+    };
+
+    let Some(format) = IconFormat::sniff(&bytes) else {
+        return Ok(None);
+    };
+    let Ok(prepared) = icons::prepare(&bytes, format.mime_type()) else {
+        return Ok(None);
+    };
+    let (format, data) = prepared.into_size(FAVICON_SIZE);
+    Ok(Some(Favicon { format, data }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A local stand-in for a website, serving fixed responses by path (404
+    /// for anything else). Returns its address and a count of requests made.
+    fn serve(pages: Vec<(&'static str, &'static str, Vec<u8>)>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let pages: HashMap<_, _> = pages
+            .into_iter()
+            .map(|(path, content_type, body)| (path, (content_type, body)))
+            .collect();
+
+        let counter = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                // Skip the headers
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                    line.clear();
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+                let (status, content_type, body) = match pages.get(path) {
+                    Some((content_type, body)) => ("200 OK", *content_type, body.as_slice()),
+                    None => ("404 Not Found", "text/plain", &b"Not found"[..]),
+                };
+                let head = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    content_type,
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(body);
+            }
+        });
+
+        (address, requests)
+    }
+
+    fn png(size: u32) -> Vec<u8> {
+        let image = image::RgbaImage::from_pixel(size, size, image::Rgba([0, 128, 255, 255]));
+        let mut bytes = Vec::new();
+        image
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        bytes
+    }
+
+    fn cache_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "pick-browser-favicons-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn size_of(favicon: &Favicon) -> u32 {
+        image::load_from_memory(&favicon.data).unwrap().width()
+    }
+
+    #[test]
+    fn finds_icon_links_largest_first() {
+        let html = r#"<html><head>
+            <LINK REL="icon" href="/small.png" sizes="16x16">
+            <link rel='shortcut icon' href='/legacy.ico'>
+            <link rel="stylesheet" href="/style.css">
+            <link href="/touch.png" rel="apple-touch-icon" sizes="180x180">
+            <link rel="mask-icon" href="/mask.svg">
+            <link rel=icon href=/scalable.svg sizes=any>
+        </head></html>"#;
+        assert_eq!(
+            icon_links(html),
+            ["/scalable.svg", "/touch.png", "/small.png", "/legacy.ico"]
+        );
+    }
+
+    #[test]
+    fn cache_keys_are_safe_file_names() {
+        let key = |url: &str| cache_key(&site_url(url).unwrap());
+        assert_eq!(key("https://GitHub.com/some/page"), "github.com");
+        assert_eq!(key("github.com"), "github.com");
+        assert_eq!(key("localhost:8080"), "localhost_8080");
+        assert_eq!(key("http://localhost:8080/"), "localhost_8080");
+        assert_eq!(key("http://[::1]:8080/"), "___1__8080");
+        assert!(site_url("file:///etc/passwd").is_err());
+        assert!(site_url("mailto:someone@example.com").is_err());
+    }
+
+    #[test]
+    fn fetches_the_linked_icon() {
+        let page = br#"<link rel="icon" href="/icons/site.png">"#.to_vec();
+        let (site, _) = serve(vec![
+            ("/", "text/html", page),
+            ("/icons/site.png", "image/png", png(64)),
+            ("/favicon.ico", "image/png", png(16)),
+        ]);
+        let dir = cache_dir("linked");
+
+        let favicon = get_favicon(&dir, &format!("{}/some/page", site))
+            .unwrap()
+            .unwrap();
+        assert_eq!(favicon.format, IconFormat::Png);
+        assert_eq!(size_of(&favicon), 32);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn falls_back_to_favicon_ico_and_caches_it() {
+        let (site, requests) = serve(vec![
+            ("/", "text/html", b"<html>No icons here</html>".to_vec()),
+            ("/favicon.ico", "image/x-icon", png(16)),
+        ]);
+        let dir = cache_dir("fallback");
+
+        let favicon = get_favicon(&dir, &site).unwrap().unwrap();
+        assert_eq!(size_of(&favicon), 16);
+        let made = requests.load(Ordering::SeqCst);
+        assert_eq!(made, 2);
+
+        // Served from the cache, without contacting the site again
+        let cached = get_favicon(&dir, &site).unwrap().unwrap();
+        assert_eq!(cached.data, favicon.data);
+        assert_eq!(requests.load(Ordering::SeqCst), made);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn remembers_sites_without_a_favicon() {
+        let page = br#"<link rel="icon" href="/broken.png">"#.to_vec();
+        let (site, requests) = serve(vec![
+            ("/", "text/html", page),
+            ("/broken.png", "image/png", b"not really a png".to_vec()),
+        ]);
+        let dir = cache_dir("missing");
+
+        assert!(get_favicon(&dir, &site).unwrap().is_none());
+        let made = requests.load(Ordering::SeqCst);
+        assert_eq!(made, 3); // The page, the linked icon and /favicon.ico
+
+        assert!(get_favicon(&dir, &site).unwrap().is_none());
+        assert_eq!(requests.load(Ordering::SeqCst), made);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn does_not_remember_unreachable_sites() {
+        // Nothing listens on a port once its listener is dropped
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let site = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let dir = cache_dir("unreachable");
+
+        let err = get_favicon(&dir, &site).err().unwrap();
+        assert!(err.starts_with("Failed to fetch the favicon of"), "{}", err);
+        let cached = std::fs::read_dir(&dir).map_or(0, |entries| entries.count());
+        assert_eq!(cached, 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rejects_oversized_favicons() {
+        let mut huge = png(16);
+        huge.resize(MAX_FAVICON_BYTES as usize + 1, 0);
+        let (site, _) = serve(vec![("/favicon.ico", "image/png", huge)]);
+        let dir = cache_dir("oversized");
+
+        assert!(get_favicon(&dir, &site).unwrap().is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,616 @@
+use crate::migrate::{host_pattern, regex_to_glob, ForeignConfig, ForeignRule};
+
+/// A value in a Finicky config. Only the declarative part of JavaScript is
+/// understood; anything else, like functions, is kept as its source text.
+#[derive(Debug, PartialEq)]
+enum Js {
+    String(String),
+    Regex(String), // Source, without the slashes and flags
+    Number(f64),
+    Bool(bool),
+    Null,
+    Array(Vec<Js>),
+    Object(Vec<(String, Js)>),
+    Call(String, Vec<Js>), // e.g. `finicky.matchHostnames([...])`
+    Other(String),
+}
+
+impl Js {
+    fn get(&self, key: &str) -> Option<&Js> {
+        match self {
+            Js::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// A short description of the value, for the report.
+    fn describe(&self) -> String {
+        let text = match self {
+            Js::Other(source) => source.split_whitespace().collect::<Vec<_>>().join(" "),
+            Js::Regex(source) => format!("/{}/", source),
+            Js::String(value) => format!("\"{}\"", value),
+            Js::Call(name, _) => format!("{}(...)", name),
+            Js::Array(_) => "an array".to_string(),
+            Js::Object(_) => "an object".to_string(),
+            Js::Number(value) => value.to_string(),
+            Js::Bool(value) => value.to_string(),
+            Js::Null => "null".to_string(),
+        };
+        if text.chars().count() > 60 {
+            format!("{}...", text.chars().take(57).collect::<String>())
+        } else {
+            text
+        }
+    }
+}
+
+/// Parses a Finicky config (`~/.finicky.js`): its default browser, and handlers
+/// whose matchers are strings, regexes or `finicky.matchHostnames(...)` and
+/// whose browser is a name or bundle id. Handlers written as functions, URL
+/// rewrites and browser options like profiles have no equivalent here.
+pub fn parse(content: &str) -> Result<ForeignConfig, String> {
+    let config = exported_config(content)?;
+    let Js::Object(entries) = &config else {
+        return Err(format!(
+            "The Finicky config exports {}, not an object",
+            config.describe()
+        ));
+    };
+
+    let mut foreign = ForeignConfig::default();
+    for (key, value) in entries {
+        match key.as_str() {
+            "defaultBrowser" => match browser_name(value) {
+                Ok(name) => foreign.default_browser = Some(name),
+                Err(reason) => foreign
+                    .unconverted
+                    .push(format!("Default browser: {}", reason)),
+            },
+            "handlers" => match value {
+                Js::Array(handlers) => {
+                    for (index, handler) in handlers.iter().enumerate() {
+                        convert_handler(handler, index, &mut foreign);
+                    }
+                }
+                other => foreign
+                    .unconverted
+                    .push(format!("Handlers: {} isn't a list", other.describe())),
+            },
+            "rewrite" => foreign.unconverted.push(
+                "URL rewrites: Finicky's are written as code, so add them as rewrite rules by hand"
+                    .to_string(),
+            ),
+            // Settings of Finicky itself, like hiding its menu bar icon
+            "options" => {}
+            other => foreign.unconverted.push(format!("Setting '{}'", other)),
+        }
+    }
+    Ok(foreign)
+}
+
+fn convert_handler(handler: &Js, index: usize, foreign: &mut ForeignConfig) {
+    let description = format!("Handler {}", index + 1);
+    let Some(matcher) = handler.get("match") else {
+        foreign
+            .unconverted
+            .push(format!("{}: it has no matcher", description));
+        return;
+    };
+    let browser = match handler.get("browser").map(browser_name) {
+        Some(Ok(name)) => name,
+        Some(Err(reason)) => {
+            foreign
+                .unconverted
+                .push(format!("{}: {}", description, reason));
+            return;
+        }
+        None => {
+            foreign
+                .unconverted
+                .push(format!("{}: it doesn't open a browser", description));
+            return;
+        }
+    };
+    if handler.get("url").is_some() {
+        foreign.unconverted.push(format!(
+            "{}: its URL rewrite, though the handler itself is converted",
+            description
+        ));
+    }
+
+    let matchers = match matcher {
+        Js::Array(matchers) => matchers.iter().collect(),
+        matcher => vec![matcher],
+    };
+    for matcher in matchers {
+        match matcher_patterns(matcher) {
+            Ok(patterns) => foreign
+                .rules
+                .extend(patterns.into_iter().map(|pattern| ForeignRule {
+                    pattern,
+                    browser: browser.clone(),
+                })),
+            Err(reason) => foreign
+                .unconverted
+                .push(format!("{}: {}", description, reason)),
+        }
+    }
+}
+
+/// Converts a Finicky matcher to glob patterns. Finicky matches strings
+/// against the whole URL, with `*` as a wildcard; without a scheme, any
+/// scheme is allowed.
+fn matcher_patterns(matcher: &Js) -> Result<Vec<String>, String> {
+    match matcher {
+        Js::String(pattern) if pattern.contains("://") => Ok(vec![pattern.clone()]),
+        Js::String(pattern) => Ok(vec![format!("*://{}", pattern)]),
+        Js::Regex(source) => regex_to_glob(source)
+            .map(|pattern| vec![pattern])
+            .ok_or_else(|| {
+                format!(
+                    "the regex {} can't be written as a pattern",
+                    matcher.describe()
+                )
+            }),
+        Js::Call(name, args)
+            if name == "finicky.matchHostnames" || name == "finicky.matchDomains" =>
+        {
+            let hosts = match args.first() {
+                Some(Js::Array(hosts)) => hosts.iter().collect(),
+                Some(host) => vec![host],
+                None => Vec::new(),
+            };
+            hosts
+                .into_iter()
+                .map(|host| match host {
+                    Js::String(host) => Ok(host_pattern(host)),
+                    Js::Regex(source) => regex_to_glob(source)
+                        .map(|host| host_pattern(host.trim_matches('*')))
+                        .ok_or_else(|| {
+                            format!(
+                                "the host regex {} can't be written as a pattern",
+                                host.describe()
+                            )
+                        }),
+                    other => Err(format!("the host {} isn't a string", other.describe())),
+                })
+                .collect()
+        }
+        other => Err(format!(
+            "the matcher {} is code, which can't be converted",
+            other.describe()
+        )),
+    }
+}
+
+/// The browser a handler opens: a name or bundle id, or an object with one.
+fn browser_name(browser: &Js) -> Result<String, String> {
+    match browser {
+        Js::String(name) => Ok(name.clone()),
+        Js::Object(_) => {
+            let Some(Js::String(name)) = browser.get("name") else {
+                return Err("its browser has no name".to_string());
+            };
+            if browser.get("profile").is_some() {
+                return Err(format!(
+                    "it opens a profile of {}, which isn't supported",
+                    name
+                ));
+            }
+            Ok(name.clone())
+        }
+        Js::Array(_) => Err("it opens several browsers; use a browser group instead".to_string()),
+        other => Err(format!(
+            "its browser {} is code, which can't be converted",
+            other.describe()
+        )),
+    }
+}
+
+/// Finds the value the config file exports, through `module.exports = ...` or
+/// `export default ...`, following it to a variable if need be.
+fn exported_config(content: &str) -> Result<Js, String> {
+    let export = ["module.exports", "export default"]
+        .iter()
+        .find_map(|marker| content.find(marker).map(|i| i + marker.len()))
+        .ok_or_else(|| "Not a Finicky config: it doesn't export anything".to_string())?;
+    let mut parser = Parser {
+        source: content,
+        position: export,
+    };
+    parser.skip_whitespace();
+    parser.eat('=');
+    let value = parser.value();
+
+    // e.g. `const config = {...}; export default config;`
+    if let Js::Other(name) = &value {
+        let name = name.trim();
+        if is_identifier(name) {
+            let declaration = regex::Regex::new(&format!(
+                r"\b(?:const|let|var)\s+{}\s*(?::[^=]+)?=",
+                regex::escape(name)
+            ))
+            .map_err(|e| format!("Failed to find variable '{}': {}", name, e))?;
+            if let Some(found) = declaration.find(content) {
+                let mut parser = Parser {
+                    source: content,
+                    position: found.end(),
+                };
+                return Ok(parser.value());
+            }
+        }
+    }
+    Ok(value)
+}
+
+fn is_identifier(text: &str) -> bool {
+    !text.is_empty()
+        && !text.starts_with(|c: char| c.is_ascii_digit())
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Parses JavaScript values, skipping over any expression it doesn't understand.
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.source[self.position..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            let rest = &self.source[self.position..];
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.position += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with("/*") {
+                self.position += trimmed.find("*/").map_or(trimmed.len(), |end| end + 2);
+            } else {
+                return;
+            }
+        }
+    }
+
+    /// Parses a value, or skips to the end of the expression if it isn't one
+    /// that is understood, keeping its source.
+    fn value(&mut self) -> Js {
+        self.skip_whitespace();
+        let start = self.position;
+        let value = self.simple_value();
+        self.skip_whitespace();
+        let ended = matches!(
+            self.peek(),
+            None | Some(',') | Some('}') | Some(']') | Some(')') | Some(';')
+        ) || self.rest().starts_with("satisfies")
+            || self.rest().starts_with("as ");
+        match value {
+            Some(value) if ended => value,
+            _ => {
+                self.position = start;
+                self.skip_expression();
+                Js::Other(self.source[start..self.position].trim().to_string())
+            }
+        }
+    }
+
+    fn simple_value(&mut self) -> Option<Js> {
+        match self.peek()? {
+            '{' => self.object(),
+            '[' => self.array(),
+            '"' | '\'' | '`' => self.string().map(Js::String),
+            '/' => self.regex(),
+            c if c.is_ascii_digit() || c == '-' => {
+                let length = self
+                    .rest()
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '-'))
+                    .unwrap_or(self.rest().len());
+                let number = self.rest()[..length].parse().ok()?;
+                self.position += length;
+                Some(Js::Number(number))
+            }
+            _ => {
+                let name = self.path();
+                match name.as_str() {
+                    "" => None,
+                    "true" => Some(Js::Bool(true)),
+                    "false" => Some(Js::Bool(false)),
+                    "null" | "undefined" => Some(Js::Null),
+                    "function" | "async" => None,
+                    _ => {
+                        self.skip_whitespace();
+                        if !self.eat('(') {
+                            return None; // A variable, which isn't followed
+                        }
+                        let args = self.list(')')?;
+                        Some(Js::Call(name, args))
+                    }
+                }
+            }
+        }
+    }
+
+    /// An identifier, or several joined by dots, e.g. `finicky.matchHostnames`.
+    fn path(&mut self) -> String {
+        let length = self
+            .rest()
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$' || c == '.'))
+            .unwrap_or(self.rest().len());
+        let name = self.rest()[..length].to_string();
+        self.position += length;
+        name
+    }
+
+    fn object(&mut self) -> Option<Js> {
+        self.eat('{');
+        let mut entries = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.eat('}') {
+                return Some(Js::Object(entries));
+            }
+            let key = match self.peek()? {
+                '"' | '\'' | '`' => self.string()?,
+                _ => self.path(),
+            };
+            if key.is_empty() {
+                return None; // e.g. a spread or computed key
+            }
+            self.skip_whitespace();
+            let value = if self.eat(':') {
+                self.value()
+            } else {
+                // A method, e.g. `match(url) { ... }`, kept as its source
+                let start = self.position;
+                self.skip_expression();
+                Js::Other(format!("{}{}", key, &self.source[start..self.position]))
+            };
+            entries.push((key, value));
+            self.skip_whitespace();
+            if !self.eat(',') && self.peek() != Some('}') {
+                return None;
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<Js> {
+        self.eat('[');
+        self.list(']').map(Js::Array)
+    }
+
+    /// Comma-separated values up to `end`, after the opening bracket.
+    fn list(&mut self, end: char) -> Option<Vec<Js>> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.eat(end) {
+                return Some(items);
+            }
+            items.push(self.value());
+            self.skip_whitespace();
+            if !self.eat(',') && self.peek() != Some(end) {
+                return None;
+            }
+        }
+    }
+
+    /// A string literal. Template literals with placeholders aren't values.
+    fn string(&mut self) -> Option<String> {
+        let quote = self.peek()?;
+        let start = self.position;
+        self.position += 1;
+        let mut value = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    let (_, escaped) = chars.next()?;
+                    value.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        other => other,
+                    });
+                }
+                '$' if quote == '`' && self.rest()[i..].starts_with("${") => {
+                    self.position = start;
+                    return None;
+                }
+                c if c == quote => {
+                    self.position += i + 1;
+                    return Some(value);
+                }
+                c => value.push(c),
+            }
+        }
+        self.position = start;
+        None
+    }
+
+    /// A regex literal, e.g. `/^https:\/\/example\.com/i`.
+    fn regex(&mut self) -> Option<Js> {
+        let start = self.position;
+        self.position += 1;
+        let mut in_class = false;
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    chars.next()?;
+                }
+                '[' => in_class = true,
+                ']' => in_class = false,
+                '\n' => break,
+                '/' if !in_class => {
+                    let source = self.rest()[..i].to_string();
+                    self.position += i + 1;
+                    // Flags don't change what globs can express, and globs ignore case anyway
+                    let flags = self
+                        .rest()
+                        .find(|c: char| !c.is_ascii_alphabetic())
+                        .unwrap_or(self.rest().len());
+                    self.position += flags;
+                    return Some(Js::Regex(source.replace("\\/", "/")));
+                }
+                _ => {}
+            }
+        }
+        self.position = start;
+        None
+    }
+
+    /// Skips to the end of the current expression: the next `,`, `;` or
+    /// closing bracket that isn't nested inside it.
+    fn skip_expression(&mut self) {
+        let mut depth = 0usize;
+        loop {
+            self.skip_whitespace();
+            let Some(c) = self.peek() else {
+                return;
+            };
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' if depth == 0 => return,
+                ')' | ']' | '}' => depth -= 1,
+                ',' | ';' if depth == 0 => return,
+                '"' | '\'' | '`' => {
+                    if self.string().is_some() {
+                        continue;
+                    }
+                    self.skip_template(c);
+                    continue;
+                }
+                _ => {}
+            }
+            self.position += c.len_utf8();
+        }
+    }
+
+    /// Skips a string that `string` couldn't read, like a template with placeholders.
+    fn skip_template(&mut self, quote: char) {
+        self.position += 1;
+        let mut escaped = false;
+        for (i, c) in self.rest().char_indices() {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == quote {
+                self.position += i + 1;
+                return;
+            }
+        }
+        self.position = self.source.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        // ~/.finicky.js
+        module.exports = {
+            defaultBrowser: "Google Chrome",
+            options: { hideIcon: false },
+            rewrite: [{ match: () => true, url: ({ url }) => url }],
+            handlers: [
+                {
+                    // Work things
+                    match: ["jira.example.com/*", /^https?:\/\/example\.slack\.com\/.*$/],
+                    browser: "Firefox",
+                },
+                {
+                    match: finicky.matchHostnames(["zoom.us", /meet\.google\.com/]),
+                    browser: { name: "com.apple.Safari" },
+                },
+                {
+                    match: ({ url }) => url.host.endsWith(".dev") && url.protocol === "https",
+                    browser: "Firefox",
+                },
+                {
+                    match: `https://${"docs"}.example.com/*`,
+                    browser: "Firefox",
+                },
+                {
+                    match: /item\/\d+/,
+                    browser: { name: "Google Chrome", profile: "Work" },
+                },
+            ],
+        };
+    "#;
+
+    #[test]
+    fn parses_declarative_handlers_and_reports_the_rest() {
+        let foreign = parse(CONFIG).unwrap();
+
+        assert_eq!(foreign.default_browser.as_deref(), Some("Google Chrome"));
+        let rules: Vec<_> = foreign
+            .rules
+            .iter()
+            .map(|r| (r.pattern.as_str(), r.browser.as_str()))
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                ("*://jira.example.com/*", "Firefox"),
+                ("http*://example.slack.com/*", "Firefox"),
+                ("*://zoom.us/*", "com.apple.Safari"),
+                ("*://meet.google.com/*", "com.apple.Safari"),
+            ]
+        );
+        assert_eq!(
+            foreign.unconverted,
+            [
+                "URL rewrites: Finicky's are written as code, so add them as rewrite rules by hand",
+                "Handler 3: the matcher ({ url }) => url.host.endsWith(\".dev\") && \
+                 url.protocol ==... is code, which can't be converted",
+                "Handler 4: the matcher `https://${\"docs\"}.example.com/*` is code, which can't \
+                 be converted",
+                "Handler 5: it opens a profile of Google Chrome, which isn't supported",
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_exported_variables() {
+        let foreign = parse(
+            r#"
+            import type { FinickyConfig } from "./finicky.d.ts";
+            const config: FinickyConfig = {
+                defaultBrowser: 'Safari',
+                handlers: [{ match: 'https://github.com/*', browser: 'Firefox' }],
+            } satisfies FinickyConfig;
+            export default config;
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(foreign.default_browser.as_deref(), Some("Safari"));
+        assert_eq!(foreign.rules[0].pattern, "https://github.com/*");
+        assert!(foreign.unconverted.is_empty());
+    }
+
+    #[test]
+    fn rejects_files_without_a_config() {
+        assert!(parse("console.log('hello');").is_err());
+        assert!(parse("module.exports = getConfig();").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::config::{Browser, Config};
+use crate::get_icons_dir;
+use crate::icons::{self, IconCache};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tauri::http::{header, HeaderValue, Request, Response, StatusCode};
+use tauri::Manager;
+
+/// The configured browsers, kept between icon requests so a page full of icons
+/// doesn't load the config for each one. They're reused only while the config
+/// files' modification times are unchanged.
+#[derive(Default)]
+pub struct BrowserCache(Mutex<Option<CachedBrowsers>>);
+
+struct CachedBrowsers {
+    sources: Vec<(PathBuf, Option<SystemTime>)>,
+    browsers: Arc<[Browser]>,
+}
+
+impl BrowserCache {
+    /// The configured browsers, from the cache if the config hasn't changed.
+    pub fn browsers(&self, app_handle: &tauri::AppHandle) -> Result<Arc<[Browser]>, String> {
+        let sources: Vec<_> = Config::source_files(app_handle)?
+            .into_iter()
+            .map(|path| {
+                let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+                (path, modified)
+            })
+            .collect();
+
+        let mut cache = self.0.lock().unwrap();
+        if let Some(cached) = cache.as_ref().filter(|cached| {
+            cached.sources == sources && sources.iter().all(|(_, modified)| modified.is_some())
+        }) {
+            return Ok(cached.browsers.clone());
+        }
+
+        let browsers: Arc<[Browser]> = Config::load(app_handle)?.browsers.into();
+        *cache = Some(CachedBrowsers {
+            sources,
+            browsers: browsers.clone(),
+        });
+        Ok(browsers)
+    }
+}
+
+/// Serves browser icons to the webview at `pickicon://localhost/<id>?size=<px>`
+/// (`http://pickicon.localhost/<id>?size=<px>` on Windows), so they load like
+/// any other image rather than being base64-encoded and sent over IPC. `size`
+/// picks the best stored size, as for `get_browser_icon`. Responses carry an
+/// ETag, so the webview only downloads an icon again after it changes.
+pub fn handle(app_handle: &tauri::AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    respond(app_handle, request).unwrap_or_else(|(status, message)| {
+        let mut response = Response::new(message.into_bytes());
+        *response.status_mut() = status;
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; charset=utf-8"),
+        );
+        response
+    })
+}
+
+fn respond(
+    app_handle: &tauri::AppHandle,
+    request: &Request<Vec<u8>>,
+) -> Result<Response<Vec<u8>>, (StatusCode, String)> {
+    let path = request.uri().path().trim_start_matches('/');
+    let id = percent_encoding::percent_decode_str(path)
+        .decode_utf8()
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid browser id '{}'", path),
+            )
+        })?;
+    let size = request
+        .uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("size="))
+        .map(|size| {
+            size.parse::<u32>()
+                .map_err(|_| (StatusCode::BAD_REQUEST, format!("Invalid size '{}'", size)))
+        })
+        .transpose()?;
+
+    let internal_error = |e: String| (StatusCode::INTERNAL_SERVER_ERROR, e);
+    let browsers = app_handle
+        .state::<BrowserCache>()
+        .browsers(app_handle)
+        .map_err(internal_error)?;
+    let browser = browsers.iter().find(|b| b.id == id).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Browser with id '{}' not found", id),
+        )
+    })?;
+    let Some(ext) = &browser.icon else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Browser '{}' has no icon", browser.name),
+        ));
+    };
+
+    let icons_dir = get_icons_dir(app_handle).map_err(internal_error)?;
+    let icon_path = icons::best_icon_path(&icons_dir, &id, ext, size);
+    if !icon_path.is_file() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Icon file for browser '{}' is missing", browser.name),
+        ));
+    }
+
+    let icon = app_handle
+        .state::<IconCache>()
+        .read(&icon_path)
+        .map_err(internal_error)?;
+    let ext = icon_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or(ext);
+
+    let not_modified = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|tag| tag.as_bytes() == icon.etag.as_bytes());
+    let mut response = if not_modified {
+        let mut response = Response::new(Vec::new());
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        response
+    } else {
+        Response::new(icon.bytes.to_vec())
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(icons::mime_for_extension(ext)),
+    );
+    // Always check the ETag, as an icon's URL stays the same when it's replaced
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    if let Ok(etag) = HeaderValue::from_str(&icon.etag) {
+        headers.insert(header::ETAG, etag);
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,922 @@
+use crate::config::Config;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits, RgbaImage};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Pixel sizes that icons are also stored at, as `<id>-<size>.png`, for those
+/// smaller than the icon itself.
+pub const ICON_SIZES: &[u32] = &[32, 64, 128, 256];
+
+/// Largest width or height an icon is stored at; bigger icons are downscaled.
+const STORED_ICON_SIZE: u32 = 256;
+
+/// Largest icon file that can be uploaded, in bytes.
+const MAX_ICON_BYTES: usize = 5 * 1024 * 1024;
+
+/// Largest width or height an uploaded image can have. This is checked before
+/// decoding, so a small file can't claim a huge image and exhaust memory.
+const MAX_ICON_DIMENSION: u32 = 4096;
+
+/// The image formats icons can be uploaded in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IconFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Avif,
+    Svg,
+    Ico,
+    Icns,
+}
+
+impl IconFormat {
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        match mime {
+            "image/png" => Some(Self::Png),
+            "image/jpeg" | "image/jpg" => Some(Self::Jpeg),
+            "image/webp" => Some(Self::WebP),
+            "image/avif" => Some(Self::Avif),
+            "image/svg+xml" => Some(Self::Svg),
+            "image/x-icon" | "image/vnd.microsoft.icon" => Some(Self::Ico),
+            "image/icns" | "image/x-icns" => Some(Self::Icns),
+            _ => None,
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "webp" => Some(Self::WebP),
+            "avif" => Some(Self::Avif),
+            "svg" => Some(Self::Svg),
+            "ico" => Some(Self::Ico),
+            "icns" => Some(Self::Icns),
+            _ => None,
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::WebP => "image/webp",
+            Self::Avif => "image/avif",
+            Self::Svg => "image/svg+xml",
+            Self::Ico => "image/x-icon",
+            Self::Icns => "image/icns",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::WebP => "webp",
+            Self::Avif => "avif",
+            Self::Svg => "svg",
+            Self::Ico => "ico",
+            Self::Icns => "icns",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Png => "PNG",
+            Self::Jpeg => "JPEG",
+            Self::WebP => "WebP",
+            Self::Avif => "AVIF",
+            Self::Svg => "SVG",
+            Self::Ico => "ICO",
+            Self::Icns => "ICNS",
+        }
+    }
+
+    /// Works out an image's format from its first bytes, ignoring what it claims to be.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(Self::Jpeg)
+        } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            Some(Self::WebP)
+        } else if is_avif(bytes) {
+            Some(Self::Avif)
+        } else if bytes.starts_with(&[0, 0, 1, 0]) {
+            Some(Self::Ico)
+        } else if bytes.starts_with(b"icns") {
+            Some(Self::Icns)
+        } else if is_svg(bytes) {
+            Some(Self::Svg)
+        } else {
+            None
+        }
+    }
+}
+
+/// AVIF files start with an `ftyp` box listing `avif` (or `avis` for
+/// animations) among their brands.
+fn is_avif(bytes: &[u8]) -> bool {
+    if bytes.len() < 16 || &bytes[4..8] != b"ftyp" {
+        return false;
+    }
+    let box_len = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    let brands = &bytes[8..box_len.clamp(8, bytes.len())];
+    brands
+        .chunks_exact(4)
+        .any(|brand| brand == b"avif" || brand == b"avis")
+}
+
+/// SVG is text, so it's recognised by starting with markup and having an `<svg` element.
+fn is_svg(bytes: &[u8]) -> bool {
+    let text = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let start = text.iter().position(|b| !b.is_ascii_whitespace());
+    start.is_some_and(|i| text[i] == b'<') && text.windows(4).any(|w| w == b"<svg")
+}
+
+/// Gets the MIME type of a stored icon from its file extension.
+pub fn mime_for_extension(ext: &str) -> &'static str {
+    IconFormat::from_extension(ext)
+        .map(IconFormat::mime_type)
+        .unwrap_or("application/octet-stream")
+}
+
+/// An icon that has been checked and normalised, ready to be saved.
+pub struct PreparedIcon {
+    format: IconFormat,         // Format of the main file
+    data: Vec<u8>,              // Main file, `<id>.<ext>`
+    sizes: Vec<(u32, Vec<u8>)>, // Smaller PNGs, `<id>-<size>.png`
+}
+
+impl PreparedIcon {
+    /// Takes the single image best suited to showing at `size` pixels: the
+    /// smallest stored size at least that big, or the main image.
+    pub fn into_size(self, size: u32) -> (IconFormat, Vec<u8>) {
+        match self.sizes.into_iter().find(|(s, _)| *s >= size) {
+            Some((_, png)) => (IconFormat::Png, png),
+            None => (self.format, self.data),
+        }
+    }
+
+    /// Writes the icon for a browser. Returns the extension of the stored icon.
+    pub fn save(&self, icons_dir: &Path, browser_id: &str) -> Result<String, String> {
+        for (size, png) in &self.sizes {
+            std::fs::write(icons_dir.join(format!("{}-{}.png", browser_id, size)), png)
+                .map_err(|e| format!("Failed to save icon: {}", e))?;
+        }
+        let ext = self.format.extension();
+        std::fs::write(
+            icons_dir.join(format!("{}.{}", browser_id, ext)),
+            &self.data,
+        )
+        .map_err(|e| format!("Failed to save icon: {}", e))?;
+
+        Ok(ext.to_string())
+    }
+}
+
+/// Checks and normalises a base64-encoded icon upload. See `prepare`.
+pub fn prepare_upload(base64_data: &str, mime: &str) -> Result<PreparedIcon, String> {
+    use base64::Engine;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64_data)
+        .map_err(|e| format!("Failed to decode icon data: {}", e))?;
+    prepare(&bytes, mime)
+}
+
+/// Checks that an icon really is the `mime` type it claims to be and isn't too
+/// large, then normalises it: everything is decoded and re-encoded as PNG no
+/// larger than STORED_ICON_SIZE, which drops any metadata (EXIF, text chunks
+/// and the like), along with a PNG for each smaller entry of ICON_SIZES. SVGs
+/// are rendered at each size. AVIF can't be decoded here, so it's refused
+/// rather than stored with its metadata intact.
+pub fn prepare(bytes: &[u8], mime: &str) -> Result<PreparedIcon, String> {
+    let format =
+        IconFormat::from_mime(mime).ok_or_else(|| format!("Unsupported image format: {}", mime))?;
+    if format == IconFormat::Avif {
+        return Err("AVIF icons aren't supported, please convert it to PNG first".to_string());
+    }
+
+    if bytes.len() > MAX_ICON_BYTES {
+        return Err(format!(
+            "Icon is too large ({} KB, the limit is {} KB)",
+            bytes.len() / 1024,
+            MAX_ICON_BYTES / 1024
+        ));
+    }
+
+    match IconFormat::sniff(bytes) {
+        Some(actual) if actual == format => {}
+        Some(actual) => {
+            return Err(format!(
+                "Icon is a {} image, but was uploaded as {}",
+                actual.name(),
+                mime
+            ))
+        }
+        None => return Err(format!("Icon isn't a valid {} image", format.name())),
+    }
+
+    let (data, sizes) = match format {
+        IconFormat::Svg => {
+            let data = encode_png(&render_svg(bytes, STORED_ICON_SIZE)?)?;
+            let sizes = ICON_SIZES
+                .iter()
+                .filter(|&&s| s < STORED_ICON_SIZE)
+                .map(|&s| Ok((s, encode_png(&render_svg(bytes, s)?)?)))
+                .collect::<Result<_, String>>()?;
+            (data, sizes)
+        }
+        _ => {
+            let image = match format {
+                IconFormat::Icns => decode_icns(bytes)?,
+                _ => decode(bytes, format)?,
+            };
+            let image = if image.width().max(image.height()) > STORED_ICON_SIZE {
+                image.resize(
+                    STORED_ICON_SIZE,
+                    STORED_ICON_SIZE,
+                    image::imageops::FilterType::Lanczos3,
+                )
+            } else {
+                image
+            };
+
+            let stored_size = image.width().max(image.height());
+            let sizes = ICON_SIZES
+                .iter()
+                .filter(|&&s| s < stored_size)
+                .map(|&s| {
+                    let resized = image.resize(s, s, image::imageops::FilterType::Lanczos3);
+                    Ok((s, encode_png(&resized.to_rgba8())?))
+                })
+                .collect::<Result<_, String>>()?;
+            (encode_png(&image.to_rgba8())?, sizes)
+        }
+    };
+
+    Ok(PreparedIcon {
+        format: IconFormat::Png,
+        data,
+        sizes,
+    })
+}
+
+/// Decodes a raster image, refusing ones larger than MAX_ICON_DIMENSION and
+/// turning JPEGs upright, as their EXIF orientation is about to be dropped.
+fn decode(bytes: &[u8], format: IconFormat) -> Result<DynamicImage, String> {
+    let image_format = match format {
+        IconFormat::Png => ImageFormat::Png,
+        IconFormat::Jpeg => ImageFormat::Jpeg,
+        IconFormat::WebP => ImageFormat::WebP,
+        IconFormat::Ico => ImageFormat::Ico,
+        _ => return Err(format!("Can't decode {} images", format.name())),
+    };
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_ICON_DIMENSION);
+    limits.max_image_height = Some(MAX_ICON_DIMENSION);
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), image_format);
+    reader.limits(limits);
+    let invalid = |e: image::ImageError| match e {
+        image::ImageError::Limits(_) => format!(
+            "Icon is too large (the limit is {}×{} pixels)",
+            MAX_ICON_DIMENSION, MAX_ICON_DIMENSION
+        ),
+        e => format!("Icon isn't a valid {} image: {}", format.name(), e),
+    };
+
+    let mut decoder = reader.into_decoder().map_err(invalid)?;
+    let orientation = decoder.orientation().map_err(invalid)?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(invalid)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// Renders an SVG to fit a `size`×`size` square, keeping its aspect ratio.
+/// The SVG is only ever rendered, never stored or shown as-is, and it can't
+/// reach anything outside itself: scripts aren't run, and images that refer
+/// to files or URLs are dropped (only embedded `data:` images are drawn).
+fn render_svg(bytes: &[u8], size: u32) -> Result<RgbaImage, String> {
+    use resvg::{tiny_skia, usvg};
+
+    let mut options = usvg::Options::default();
+    options.image_href_resolver.resolve_string = Box::new(|_, _| None);
+
+    let tree = usvg::Tree::from_data(bytes, &options)
+        .map_err(|e| format!("Failed to parse SVG icon: {}", e))?;
+
+    let mut pixmap =
+        tiny_skia::Pixmap::new(size, size).ok_or_else(|| format!("Invalid icon size: {}", size))?;
+    let svg_size = tree.size();
+    let scale = size as f32 / svg_size.width().max(svg_size.height());
+    let offset_x = (size as f32 - svg_size.width() * scale) / 2.0;
+    let offset_y = (size as f32 - svg_size.height() * scale) / 2.0;
+    let transform =
+        tiny_skia::Transform::from_scale(scale, scale).post_translate(offset_x, offset_y);
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia works with premultiplied alpha, PNG expects straight alpha
+    let rgba = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+    RgbaImage::from_raw(size, size, rgba).ok_or_else(|| "Failed to render SVG icon".to_string())
+}
+
+/// Decodes the largest PNG image in a macOS `.icns` file. Since macOS 10.7,
+/// every size from 16px up can be stored as PNG; older formats aren't supported.
+fn decode_icns(bytes: &[u8]) -> Result<DynamicImage, String> {
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    if bytes.len() < 8 || &bytes[..4] != b"icns" {
+        return Err("Not an ICNS icon".to_string());
+    }
+
+    // The file is a list of (4-byte type, 4-byte big-endian length including the header, data)
+    let mut best: Option<DynamicImage> = None;
+    let mut offset = 8;
+    while offset + 8 <= bytes.len() {
+        let length = u32::from_be_bytes([
+            bytes[offset + 4],
+            bytes[offset + 5],
+            bytes[offset + 6],
+            bytes[offset + 7],
+        ]) as usize;
+        if length < 8 || offset + length > bytes.len() {
+            break;
+        }
+
+        let data = &bytes[offset + 8..offset + length];
+        if data.starts_with(PNG_SIGNATURE) {
+            let image = decode(data, IconFormat::Png)?;
+            if best.as_ref().is_none_or(|b| image.width() > b.width()) {
+                best = Some(image);
+            }
+        }
+        offset += length;
+    }
+
+    best.ok_or_else(|| "ICNS icon has no PNG images".to_string())
+}
+
+fn encode_png(image: &RgbaImage) -> Result<Vec<u8>, String> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode icon: {}", e))?;
+    Ok(png)
+}
+
+/// Picks the icon file to serve for a browser: the smallest stored size at
+/// least `size` pixels across, falling back to the main icon file, which is
+/// always the largest.
+pub fn best_icon_path(icons_dir: &Path, browser_id: &str, ext: &str, size: Option<u32>) -> PathBuf {
+    size.and_then(|wanted| {
+        ICON_SIZES
+            .iter()
+            .filter(|&&s| s >= wanted)
+            .map(|s| icons_dir.join(format!("{}-{}.png", browser_id, s)))
+            .find(|path| path.is_file())
+    })
+    .unwrap_or_else(|| icons_dir.join(format!("{}.{}", browser_id, ext)))
+}
+
+/// Removes every file stored for a browser's icon, including the smaller sizes.
+/// Files that are already gone are fine.
+pub fn remove_icon_files(icons_dir: &Path, browser_id: &str, ext: &str) -> Result<(), String> {
+    let sizes = ICON_SIZES
+        .iter()
+        .map(|size| format!("{}-{}.png", browser_id, size));
+    for name in std::iter::once(format!("{}.{}", browser_id, ext)).chain(sizes) {
+        remove_file(&icons_dir.join(name))?;
+    }
+    Ok(())
+}
+
+fn remove_file(path: &Path) -> Result<(), String> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Failed to remove icon '{}': {}", path.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// What `clean_up` changed.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IconCleanup {
+    pub removed_files: Vec<String>, // Files in the icons directory no browser uses
+    pub repaired_browsers: Vec<String>, // IDs of browsers whose icon file was missing
+}
+
+/// Brings the icons directory and the config back in line, e.g. after the
+/// config was edited by hand: browsers whose icon file is missing no longer
+/// claim to have one, and files that don't belong to a browser's icon are
+/// removed. Every icon file of a browser from the system config is kept, as
+/// the icon detected for it isn't saved. The config is changed in place;
+/// saving it is up to the caller.
+pub fn clean_up(config: &mut Config, icons_dir: &Path) -> Result<IconCleanup, String> {
+    let mut cleanup = IconCleanup::default();
+
+    for browser in &mut config.browsers {
+        let missing = browser
+            .icon
+            .as_ref()
+            .is_some_and(|ext| !icons_dir.join(format!("{}.{}", browser.id, ext)).is_file());
+        if missing {
+            browser.icon = None;
+            cleanup.repaired_browsers.push(browser.id.clone());
+        }
+    }
+
+    let entries = match std::fs::read_dir(icons_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(cleanup),
+        Err(e) => return Err(format!("Failed to read icons directory: {}", e)),
+    };
+
+    let mut in_use = std::collections::HashSet::new();
+    for browser in &config.browsers {
+        let Some(ext) = &browser.icon else {
+            continue;
+        };
+        in_use.insert(format!("{}.{}", browser.id, ext));
+        in_use.extend(
+            ICON_SIZES
+                .iter()
+                .map(|size| format!("{}-{}.png", browser.id, size)),
+        );
+    }
+    let is_locked_icon = |name: &str| {
+        config.browsers.iter().filter(|b| b.locked).any(|b| {
+            name.strip_prefix(b.id.as_str()).is_some_and(|rest| {
+                let is_sized = |size: &u32| rest == format!("-{}.png", size);
+                rest.strip_prefix('.')
+                    .is_some_and(|ext| IconFormat::from_extension(ext).is_some())
+                    || ICON_SIZES.iter().any(is_sized)
+            })
+        })
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !entry.path().is_file() || in_use.contains(&name) || is_locked_icon(&name) {
+            continue;
+        }
+        remove_file(&entry.path())?;
+        cleanup.removed_files.push(name);
+    }
+    cleanup.removed_files.sort();
+
+    Ok(cleanup)
+}
+
+/// Icon files that have already been read, so showing the browser list
+/// doesn't read every file again. Entries are keyed by path and reused only
+/// while the file's modification time is unchanged.
+#[derive(Default)]
+pub struct IconCache(Mutex<HashMap<PathBuf, CachedIcon>>);
+
+struct CachedIcon {
+    modified: Option<SystemTime>,
+    bytes: Arc<[u8]>,
+    base64: Option<String>, // Encoded the first time it's asked for
+}
+
+/// The contents of an icon file, as read through `IconCache`.
+pub struct IconFile {
+    pub bytes: Arc<[u8]>,
+    pub etag: String, // Changes whenever the file does
+}
+
+impl IconCache {
+    /// Reads an icon file, from the cache if it hasn't changed.
+    pub fn read(&self, path: &Path) -> Result<IconFile, String> {
+        self.with_entry(path, |entry| {
+            let modified = entry
+                .modified
+                .and_then(|m| m.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            IconFile {
+                bytes: entry.bytes.clone(),
+                etag: format!("\"{:x}-{:x}\"", modified.as_nanos(), entry.bytes.len()),
+            }
+        })
+    }
+
+    /// Reads an icon file as base64, from the cache if it hasn't changed.
+    pub fn read_base64(&self, path: &Path) -> Result<String, String> {
+        self.with_entry(path, |entry| {
+            use base64::Engine;
+            entry
+                .base64
+                .get_or_insert_with(|| {
+                    base64::engine::general_purpose::STANDARD.encode(&entry.bytes)
+                })
+                .clone()
+        })
+    }
+
+    fn with_entry<T>(
+        &self,
+        path: &Path,
+        f: impl FnOnce(&mut CachedIcon) -> T,
+    ) -> Result<T, String> {
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+        let mut cache = self.0.lock().unwrap();
+        let fresh = cache
+            .get(path)
+            .is_some_and(|cached| modified.is_some() && cached.modified == modified);
+        if !fresh {
+            let bytes = std::fs::read(path).map_err(|e| format!("Failed to read icon: {}", e))?;
+            cache.insert(
+                path.to_path_buf(),
+                CachedIcon {
+                    modified,
+                    bytes: bytes.into(),
+                    base64: None,
+                },
+            );
+        }
+
+        let entry = cache.get_mut(path).expect("icon was just cached");
+        Ok(f(entry))
+    }
+
+    /// Forgets every cached icon, for when icon files are written or removed.
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([(x % 256) as u8, (y % 256) as u8, 128, 255])
+        }))
+    }
+
+    fn encode(image: &DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match format {
+            // The JPEG encoder doesn't take an alpha channel
+            ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_to(&mut Cursor::new(&mut bytes), format),
+            _ => image.write_to(&mut Cursor::new(&mut bytes), format),
+        }
+        .unwrap();
+        bytes
+    }
+
+    fn decoded(png: &[u8]) -> DynamicImage {
+        image::load_from_memory_with_format(png, ImageFormat::Png).unwrap()
+    }
+
+    fn sizes(icon: &PreparedIcon) -> Vec<u32> {
+        icon.sizes.iter().map(|(s, _)| *s).collect()
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    /// CRC-32 as used by PNG chunks, to build test files with extra chunks.
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = 0xFFFF_FFFFu32;
+        for &byte in bytes {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+
+    /// Inserts a `tEXt` chunk after a PNG's header chunk.
+    fn with_png_text(png: &[u8], text: &[u8]) -> Vec<u8> {
+        let ihdr_end = 8 + 4 + 4 + 13 + 4;
+        let mut chunk = b"tEXt".to_vec();
+        chunk.extend_from_slice(text);
+
+        let mut out = png[..ihdr_end].to_vec();
+        out.extend((text.len() as u32).to_be_bytes());
+        out.extend(&chunk);
+        out.extend(crc32(&chunk).to_be_bytes());
+        out.extend(&png[ihdr_end..]);
+        out
+    }
+
+    /// Builds an ICNS file holding the given PNGs.
+    fn icns(pngs: &[Vec<u8>]) -> Vec<u8> {
+        let entries: Vec<u8> = pngs
+            .iter()
+            .flat_map(|png| {
+                let mut entry = b"ic10".to_vec();
+                entry.extend(((png.len() + 8) as u32).to_be_bytes());
+                entry.extend(png);
+                entry
+            })
+            .collect();
+        let mut out = b"icns".to_vec();
+        out.extend(((entries.len() + 8) as u32).to_be_bytes());
+        out.extend(entries);
+        out
+    }
+
+    const AVIF_HEADER: &[u8] =
+        b"\x00\x00\x00\x1cftypavif\x00\x00\x00\x00avifmif1miaf\x00\x00\x00\x00meta";
+
+    #[test]
+    fn png_is_stripped_of_metadata() {
+        let png = encode(&test_image(48, 48), ImageFormat::Png);
+        let png = with_png_text(&png, b"Author\0Someone");
+        assert!(contains(&png, b"tEXt"));
+
+        let icon = prepare(&png, "image/png").unwrap();
+        assert_eq!(icon.format, IconFormat::Png);
+        assert!(!contains(&icon.data, b"tEXt"));
+        assert!(!contains(&icon.data, b"Someone"));
+        assert_eq!(decoded(&icon.data).width(), 48);
+        assert_eq!(sizes(&icon), [32]);
+    }
+
+    #[test]
+    fn large_png_is_downscaled() {
+        let png = encode(&test_image(1024, 512), ImageFormat::Png);
+        let icon = prepare(&png, "image/png").unwrap();
+
+        let stored = decoded(&icon.data);
+        assert_eq!((stored.width(), stored.height()), (256, 128));
+        assert_eq!(sizes(&icon), [32, 64, 128]);
+        assert_eq!(decoded(&icon.sizes[0].1).width(), 32);
+    }
+
+    #[test]
+    fn jpeg_is_converted_to_png_without_exif() {
+        let jpeg = encode(&test_image(64, 64), ImageFormat::Jpeg);
+        // Add an EXIF segment straight after the start-of-image marker
+        let exif = b"Exif\0\0secret-camera";
+        let mut with_exif = jpeg[..2].to_vec();
+        with_exif.extend([0xFF, 0xE1]);
+        with_exif.extend(((exif.len() + 2) as u16).to_be_bytes());
+        with_exif.extend(exif);
+        with_exif.extend(&jpeg[2..]);
+
+        let icon = prepare(&with_exif, "image/jpeg").unwrap();
+        assert_eq!(icon.format, IconFormat::Png);
+        assert!(!contains(&icon.data, b"secret-camera"));
+        assert_eq!(decoded(&icon.data).width(), 64);
+    }
+
+    #[test]
+    fn webp_is_converted_to_png() {
+        let webp = encode(&test_image(40, 20), ImageFormat::WebP);
+        let icon = prepare(&webp, "image/webp").unwrap();
+
+        let stored = decoded(&icon.data);
+        assert_eq!((stored.width(), stored.height()), (40, 20));
+        assert_eq!(sizes(&icon), [32]);
+    }
+
+    #[test]
+    fn avif_is_refused() {
+        let err = prepare(AVIF_HEADER, "image/avif").err().unwrap();
+        assert_eq!(
+            err,
+            "AVIF icons aren't supported, please convert it to PNG first"
+        );
+
+        // Even when it's labelled as something else
+        let err = prepare(AVIF_HEADER, "image/png").err().unwrap();
+        assert_eq!(err, "Icon is a AVIF image, but was uploaded as image/png");
+    }
+
+    #[test]
+    fn svg_is_rendered_at_every_size() {
+        let svg = br#"<?xml version="1.0"?>
+            <svg xmlns="http://www.w3.org/2000/svg" width="20" height="10">
+                <rect width="20" height="10" fill="red"/>
+            </svg>"#;
+        let icon = prepare(svg, "image/svg+xml").unwrap();
+
+        let stored = decoded(&icon.data).to_rgba8();
+        assert_eq!(stored.dimensions(), (256, 256));
+        // Centred, keeping the aspect ratio
+        assert_eq!(stored.get_pixel(0, 0)[3], 0);
+        assert_eq!(stored.get_pixel(128, 128).0, [255, 0, 0, 255]);
+        assert_eq!(sizes(&icon), [32, 64, 128]);
+    }
+
+    #[test]
+    fn svg_cannot_load_external_images() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10">
+                <script>alert(1)</script>
+                <image href="file:///etc/passwd" width="10" height="10"/>
+                <image href="https://example.com/a.png" width="10" height="10"/>
+            </svg>"#;
+        let icon = prepare(svg, "image/svg+xml").unwrap();
+        let stored = decoded(&icon.data).to_rgba8();
+        assert!(stored.pixels().all(|p| p[3] == 0));
+    }
+
+    #[test]
+    fn ico_uses_the_largest_image() {
+        let ico = encode(&test_image(48, 48), ImageFormat::Ico);
+        let icon = prepare(&ico, "image/x-icon").unwrap();
+        assert_eq!(icon.format, IconFormat::Png);
+        assert_eq!(decoded(&icon.data).width(), 48);
+
+        let icon = prepare(&ico, "image/vnd.microsoft.icon").unwrap();
+        assert_eq!(decoded(&icon.data).width(), 48);
+    }
+
+    #[test]
+    fn icns_uses_the_largest_png() {
+        let small = encode(&test_image(16, 16), ImageFormat::Png);
+        let large = encode(&test_image(512, 512), ImageFormat::Png);
+        let icon = prepare(&icns(&[small, large]), "image/icns").unwrap();
+
+        assert_eq!(decoded(&icon.data).width(), 256);
+        assert_eq!(sizes(&icon), [32, 64, 128]);
+
+        assert!(prepare(&icns(&[]), "image/icns").is_err());
+    }
+
+    #[test]
+    fn mismatched_type_is_rejected() {
+        let png = encode(&test_image(8, 8), ImageFormat::Png);
+        let err = prepare(&png, "image/jpeg").err().unwrap();
+        assert_eq!(err, "Icon is a PNG image, but was uploaded as image/jpeg");
+
+        assert!(prepare(b"<html><body></body></html>", "image/svg+xml").is_err());
+        assert!(prepare(b"not an image", "image/png").is_err());
+    }
+
+    #[test]
+    fn unsupported_type_is_rejected() {
+        let err = prepare(b"GIF89a\x01\x00\x01\x00", "image/gif")
+            .err()
+            .unwrap();
+        assert_eq!(err, "Unsupported image format: image/gif");
+    }
+
+    #[test]
+    fn oversized_icons_are_rejected() {
+        let mut huge = b"\x89PNG\r\n\x1a\n".to_vec();
+        huge.resize(MAX_ICON_BYTES + 1, 0);
+        assert!(prepare(&huge, "image/png")
+            .err()
+            .unwrap()
+            .contains("too large"));
+
+        // All one colour, so the file is small but the image isn't
+        let wide = DynamicImage::ImageRgba8(RgbaImage::new(MAX_ICON_DIMENSION + 1, 1));
+        let png = encode(&wide, ImageFormat::Png);
+        assert!(png.len() < MAX_ICON_BYTES);
+        assert!(prepare(&png, "image/png")
+            .err()
+            .unwrap()
+            .contains("too large"));
+    }
+
+    #[test]
+    fn sniffs_each_format() {
+        let image = test_image(4, 4);
+        let sniff = |format| IconFormat::sniff(&encode(&image, format));
+        assert_eq!(sniff(ImageFormat::Png), Some(IconFormat::Png));
+        assert_eq!(sniff(ImageFormat::Jpeg), Some(IconFormat::Jpeg));
+        assert_eq!(sniff(ImageFormat::WebP), Some(IconFormat::WebP));
+        assert_eq!(sniff(ImageFormat::Ico), Some(IconFormat::Ico));
+        assert_eq!(IconFormat::sniff(AVIF_HEADER), Some(IconFormat::Avif));
+        assert_eq!(IconFormat::sniff(&icns(&[])), Some(IconFormat::Icns));
+        assert_eq!(
+            IconFormat::sniff(b"\xEF\xBB\xBF  <svg/>"),
+            Some(IconFormat::Svg)
+        );
+        assert_eq!(IconFormat::sniff(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn maps_extensions_and_mime_types() {
+        assert_eq!(mime_for_extension("jpg"), "image/jpeg");
+        assert_eq!(mime_for_extension("PNG"), "image/png");
+        assert_eq!(mime_for_extension("bin"), "application/octet-stream");
+
+        let formats = [
+            IconFormat::Png,
+            IconFormat::Jpeg,
+            IconFormat::WebP,
+            IconFormat::Avif,
+            IconFormat::Svg,
+            IconFormat::Ico,
+            IconFormat::Icns,
+        ];
+        for format in formats {
+            assert_eq!(IconFormat::from_mime(format.mime_type()), Some(format));
+            assert_eq!(IconFormat::from_extension(format.extension()), Some(format));
+        }
+    }
+
+    #[test]
+    fn picks_the_best_stored_size() {
+        let dir = std::env::temp_dir().join(format!("pick-browser-icons-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let png = encode(&test_image(100, 100), ImageFormat::Png);
+        let ext = prepare(&png, "image/png").unwrap().save(&dir, "b").unwrap();
+        assert_eq!(ext, "png");
+
+        let pick = |size| best_icon_path(&dir, "b", &ext, size);
+        assert_eq!(pick(Some(20)), dir.join("b-32.png"));
+        assert_eq!(pick(Some(64)), dir.join("b-64.png"));
+        assert_eq!(pick(Some(100)), dir.join("b.png"));
+        assert_eq!(pick(None), dir.join("b.png"));
+
+        remove_icon_files(&dir, "b", &ext).unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        // Already removed files aren't an error
+        remove_icon_files(&dir, "b", &ext).unwrap();
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn clean_up_removes_orphans_and_repairs_missing_icons() {
+        let dir = std::env::temp_dir().join(format!("pick-browser-gc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let browser = |id: &str, icon: Option<&str>| crate::config::Browser {
+            icon: icon.map(str::to_string),
+            ..crate::config::test_support::browser(id, id)
+        };
+        let mut config = Config {
+            browsers: vec![
+                browser("kept", Some("png")),
+                browser("missing", Some("webp")),
+                browser("none", None),
+                crate::config::Browser {
+                    locked: true,
+                    ..browser("system", None)
+                },
+            ],
+            ..Default::default()
+        };
+        for name in [
+            "kept.png",
+            "kept-32.png",
+            "kept.jpg",    // Left over from an earlier icon
+            "deleted.png", // Browser no longer exists
+            "deleted-64.png",
+            "none.png",
+            "missing-32.png",
+            "system.png", // Detected for a browser from the system config, so not saved
+            "system-64.png",
+            "system.txt",
+        ] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let cleanup = clean_up(&mut config, &dir).unwrap();
+        assert_eq!(cleanup.repaired_browsers, ["missing"]);
+        assert_eq!(config.browsers[1].icon, None);
+        assert_eq!(
+            cleanup.removed_files,
+            [
+                "deleted-64.png",
+                "deleted.png",
+                "kept.jpg",
+                "missing-32.png",
+                "none.png",
+                "system.txt"
+            ]
+        );
+
+        let mut left: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            ["kept-32.png", "kept.png", "system-64.png", "system.png"]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
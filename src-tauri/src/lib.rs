@@ -1,8 +1,45 @@
+mod apps;
+mod bundle;
 mod config;
-
-use config::{copy_bundled_icon, get_known_browser_asset, Browser, Config, Rule};
+mod config_format;
+mod conflicts;
+mod countdown;
+mod desktop_icons;
+mod expand;
+mod explain;
+mod favicons;
+mod finicky;
+mod icon_protocol;
+mod icons;
+mod link_files;
+mod migrate;
+mod pipeline;
+mod schemes;
+mod snooze;
+mod suggestions;
+mod tracking;
+mod unwrap;
+mod validate;
+
+use bundle::{ImportMode, ImportReport};
+use config::{
+    detect_browser_icon, AppTarget, Browser, BrowserGroup, Config, FallbackSettings, RewriteRule,
+    Rule,
+};
+use config_format::ConfigFormat;
+use conflicts::RuleWarning;
+use countdown::{Countdown, CountdownTick};
+use explain::Explanation;
+use icon_protocol::BrowserCache;
+use icons::{IconCache, IconCleanup};
+use migrate::MigrationReport;
+use pipeline::PipelineResult;
+use snooze::{OverrideState, TemporaryOverride};
+use std::collections::HashMap;
 use std::process::Command;
 use std::sync::Mutex;
+use suggestions::RuleSuggestion;
+use validate::ConfigIssue;
 
 #[cfg(target_os = "macos")]
 const BUNDLE_ID: &str = "website.peterreeves.pick-browser";
@@ -10,6 +47,42 @@ const BUNDLE_ID: &str = "website.peterreeves.pick-browser";
 /// Stores the URL received via Apple Events (macOS) so the frontend can retrieve it.
 struct OpenedUrl(Mutex<Option<String>>);
 
+/// Where a rule sends matching URLs.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "kind", content = "id", rename_all = "lowercase")]
+enum RuleTarget {
+    Browser(String),
+    App(String),
+    Group(String),
+}
+
+/// What happens to the rules that open in a browser when it is deleted.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(tag = "action", content = "browser_id", rename_all = "lowercase")]
+enum OnDeleteRules {
+    #[default]
+    Prompt, // Prompt to choose instead
+    Remove,           // Delete the rules
+    Reassign(String), // Open in another browser instead
+}
+
+/// The outcome of launching one browser when opening a URL in several at once.
+#[derive(Debug, Clone, serde::Serialize)]
+struct LaunchResult {
+    browser_id: String,
+    error: Option<String>, // Set if the browser failed to launch
+}
+
+/// Everything that would stop opening URLs in a browser if it were deleted.
+#[derive(Debug, Clone, serde::Serialize)]
+struct BrowserUsage {
+    rules: Vec<Rule>,       // Rules that open URLs in it
+    group_rules: Vec<Rule>, // Rules that open URLs in a group it is in
+    groups: Vec<String>,    // Names of the groups it is in
+    fallback: bool,         // Whether it is the fallback browser
+    override_active: bool,  // Whether a temporary override opens everything in it
+}
+
 #[tauri::command]
 fn get_browsers(app_handle: tauri::AppHandle) -> Result<Vec<Browser>, String> {
     let config = Config::load(&app_handle)?;
@@ -92,12 +165,21 @@ async fn is_default_browser() -> Result<bool, String> {
 
     #[cfg(not(any(target_os = "windows", target_os = "macos")))]
     {
-        Ok(false)
+        let output = Command::new("xdg-mime")
+            .args(["query", "default", "x-scheme-handler/https"])
+            .output()
+            .map_err(|e| format!("Failed to run xdg-mime: {}", e))?;
+        Ok(String::from_utf8_lossy(&output.stdout).trim() == schemes::DESKTOP_ENTRY_NAME)
     }
 }
 
+/// Register Pick Browser as the handler for http/https, plus any extra schemes from the config
+/// (mailto, tel, custom schemes like zoommtg, ...).
 #[tauri::command]
-async fn make_default_browser() -> Result<(), String> {
+async fn make_default_browser(app_handle: tauri::AppHandle) -> Result<(), String> {
+    let config = Config::load(&app_handle)?;
+    let schemes = schemes::registered_schemes(&config.schemes);
+
     #[cfg(target_os = "windows")]
     {
         use std::env;
@@ -145,12 +227,11 @@ async fn make_default_browser() -> Result<(), String> {
             .create_subkey(url_assoc_path)
             .map_err(|e| format!("Failed to create URL associations key: {}", e))?;
 
-        url_key
-            .set_value("http", &"PickBrowserURL")
-            .map_err(|e| format!("Failed to set http association: {}", e))?;
-        url_key
-            .set_value("https", &"PickBrowserURL")
-            .map_err(|e| format!("Failed to set https association: {}", e))?;
+        for scheme in &schemes {
+            url_key
+                .set_value(scheme, &"PickBrowserURL")
+                .map_err(|e| format!("Failed to set {} association: {}", scheme, e))?;
+        }
 
         // Register the ProgID for URL handling
         let prog_id_path = r"Software\Classes\PickBrowserURL";
@@ -176,6 +257,32 @@ async fn make_default_browser() -> Result<(), String> {
             .set_value("", &command)
             .map_err(|e| format!("Failed to set command: {}", e))?;
 
+        // Custom schemes nothing else handles yet (e.g. zoommtg) need their own URL protocol
+        // class, otherwise Windows won't launch anything for them. Existing ones are left alone.
+        let hkcr = RegKey::predef(HKEY_CLASSES_ROOT);
+        for scheme in &schemes {
+            if schemes::WEB_SCHEMES.contains(&scheme.as_str()) || hkcr.open_subkey(scheme).is_ok() {
+                continue;
+            }
+
+            let (scheme_key, _) = hkcu
+                .create_subkey(format!(r"Software\Classes\{}", scheme))
+                .map_err(|e| format!("Failed to create {} protocol key: {}", scheme, e))?;
+            scheme_key
+                .set_value("", &format!("URL:{} Protocol", scheme))
+                .map_err(|e| format!("Failed to set {} protocol name: {}", scheme, e))?;
+            scheme_key
+                .set_value("URL Protocol", &"")
+                .map_err(|e| format!("Failed to set {} URL Protocol: {}", scheme, e))?;
+
+            let (scheme_cmd_key, _) = scheme_key
+                .create_subkey(r"shell\open\command")
+                .map_err(|e| format!("Failed to create {} command key: {}", scheme, e))?;
+            scheme_cmd_key
+                .set_value("", &command)
+                .map_err(|e| format!("Failed to set {} command: {}", scheme, e))?;
+        }
+
         // Register in RegisteredApplications
         let reg_apps_path = r"Software\RegisteredApplications";
         let (reg_apps_key, _) = hkcu
@@ -210,7 +317,11 @@ async fn make_default_browser() -> Result<(), String> {
 
         let bundle_id = CFString::new(BUNDLE_ID);
 
-        for scheme in &["http", "https"] {
+        // Schemes the app bundle doesn't declare can't be handled, e.g. ones added on another platform
+        for scheme in schemes
+            .iter()
+            .filter(|s| schemes::check_registrable(s).is_ok())
+        {
             let scheme_cf = CFString::new(scheme);
             // SAFETY: Both `scheme_cf` and `bundle_id` are valid CFStrings that
             // outlive this call. LSSetDefaultHandlerForURLScheme is a stable
@@ -234,21 +345,75 @@ async fn make_default_browser() -> Result<(), String> {
 
     #[cfg(not(any(target_os = "windows", target_os = "macos")))]
     {
-        Err("Setting default browser is not supported on this platform".to_string())
+        use std::env;
+        use tauri::Manager;
+
+        let exe_path =
+            env::current_exe().map_err(|e| format!("Failed to get executable path: {}", e))?;
+
+        // Install a desktop entry declaring the schemes, in the user's applications directory
+        let data_home = match env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => std::path::PathBuf::from(dir),
+            _ => app_handle
+                .path()
+                .home_dir()
+                .map_err(|e| format!("Failed to get home directory: {}", e))?
+                .join(".local/share"),
+        };
+        let applications_dir = data_home.join("applications");
+        std::fs::create_dir_all(&applications_dir)
+            .map_err(|e| format!("Failed to create applications directory: {}", e))?;
+        std::fs::write(
+            applications_dir.join(schemes::DESKTOP_ENTRY_NAME),
+            schemes::desktop_entry(&exe_path.to_string_lossy(), &schemes),
+        )
+        .map_err(|e| format!("Failed to write desktop entry: {}", e))?;
+        // Only refreshes the MIME cache, so it doesn't matter if it's not installed
+        let _ = Command::new("update-desktop-database")
+            .arg(&applications_dir)
+            .status();
+
+        // Then make it the default handler for each scheme
+        for scheme in &schemes {
+            let status = Command::new("xdg-mime")
+                .args([
+                    "default",
+                    schemes::DESKTOP_ENTRY_NAME,
+                    &format!("x-scheme-handler/{}", scheme),
+                ])
+                .status()
+                .map_err(|e| format!("Failed to run xdg-mime: {}", e))?;
+            if !status.success() {
+                return Err(format!("Failed to set default handler for {}", scheme));
+            }
+        }
+
+        Ok(())
     }
 }
 
 #[tauri::command]
-async fn url_to_open(state: tauri::State<'_, OpenedUrl>) -> Result<String, String> {
+async fn url_to_open(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, OpenedUrl>,
+) -> Result<String, String> {
     // Check for URL received via Apple Events (macOS)
     if let Some(url) = state.0.lock().unwrap().as_ref() {
-        return Ok(url.clone());
+        return Ok(link_files::resolve_file(url)?.unwrap_or_else(|| url.clone()));
     }
 
-    // Fall back to command-line arguments (Windows)
+    // Fall back to command-line arguments (Windows, Linux), accepting any registered scheme.
+    // Local files are opened as file:// URLs, and link files (.url, .webloc, .desktop) as
+    // the URL they point to. If the config can't be loaded, web links still open.
+    let extra_schemes = Config::load(&app_handle)
+        .map(|config| config.schemes)
+        .unwrap_or_default();
     let args: Vec<String> = std::env::args().collect();
     for arg in args.iter().skip(1) {
-        if arg.starts_with("http://") || arg.starts_with("https://") {
+        if let Some(url) = link_files::resolve_file(arg)? {
+            return Ok(url);
+        }
+        if schemes::is_openable_url(arg, &extra_schemes) {
             return Ok(arg.clone());
         }
     }
@@ -261,20 +426,105 @@ async fn open_url_in_browser(
     url: String,
     id: String,
     close: bool,
+    manual: bool, // True when the user picked the browser, rather than a rule
 ) -> Result<(), String> {
-    let config = Config::load(&app_handle)?;
+    let mut config = Config::load(&app_handle)?;
+    let url = launch_browser(&config, &url, &id)?;
+
+    // Remember manual picks so repeated choices can be suggested as rules
+    if manual {
+        suggestions::record_pick(&mut config, &url, &id);
+        config.save(&app_handle)?;
+    }
+
+    if close {
+        app_handle.exit(0);
+    }
+
+    Ok(())
+}
 
+/// Launch a browser with a URL, stripping tracking parameters where enabled.
+/// Returns the URL that was actually opened.
+fn launch_browser(config: &Config, url: &str, id: &str) -> Result<String, String> {
     let browser = config
         .browsers
         .iter()
         .find(|b| b.id == id)
         .ok_or_else(|| format!("Browser with id '{}' not found", id))?;
 
+    let url = tracking::strip_for_browser(config, browser, url);
+
     Command::new(&browser.path)
         .arg(&url)
         .spawn()
         .map_err(|e| format!("Failed to open browser '{}': {}", browser.name, e))?;
 
+    Ok(url)
+}
+
+/// Open a URL in several browsers at once: either the given browsers, or every browser in a
+/// group. Each browser is launched even if another fails, and the result of each is returned.
+/// Only closes if every browser launched.
+#[tauri::command]
+async fn open_url_in_browsers(
+    app_handle: tauri::AppHandle,
+    url: String,
+    ids: Option<Vec<String>>,
+    group_id: Option<String>, // Used instead of ids if set
+    close: bool,
+) -> Result<Vec<LaunchResult>, String> {
+    let config = Config::load(&app_handle)?;
+
+    let ids = match group_id {
+        Some(group_id) => config
+            .groups
+            .iter()
+            .find(|g| g.id == group_id)
+            .ok_or_else(|| format!("Browser group with id '{}' not found", group_id))?
+            .browser_ids
+            .clone(),
+        None => ids.unwrap_or_default(),
+    };
+    if ids.is_empty() {
+        return Err("No browsers to open the URL in".to_string());
+    }
+
+    let results: Vec<LaunchResult> = ids
+        .into_iter()
+        .map(|id| LaunchResult {
+            error: launch_browser(&config, &url, &id).err(),
+            browser_id: id,
+        })
+        .collect();
+
+    if close && results.iter().all(|r| r.error.is_none()) {
+        app_handle.exit(0);
+    }
+
+    Ok(results)
+}
+
+/// Open a URL in a native application, transforming it into the application's own URI first.
+#[tauri::command]
+async fn open_url_in_app(
+    app_handle: tauri::AppHandle,
+    url: String,
+    id: String,
+    close: bool,
+) -> Result<(), String> {
+    let config = Config::load(&app_handle)?;
+
+    let app = config
+        .apps
+        .iter()
+        .find(|a| a.id == id)
+        .ok_or_else(|| format!("Application with id '{}' not found", id))?;
+
+    let uri = apps::transform_url(&url, app)
+        .ok_or_else(|| format!("URL can't be opened in application '{}'", app.name))?;
+    apps::launch(app, &uri)?;
+
     if close {
         app_handle.exit(0);
     }
@@ -324,13 +574,17 @@ pub struct BrowserIcon {
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn add_new_browser(
     app_handle: tauri::AppHandle,
     name: String,
     path: String,
     icon: Option<String>,      // Base64-encoded image data
     icon_mime: Option<String>, // MIME type like "image/png"
+    strip_tracking: Option<bool>,
+    schemes: Option<Vec<String>>, // URL schemes this target handles (None/empty = web links and files)
 ) -> Result<(), String> {
+    let schemes = normalise_schemes(schemes.unwrap_or_default())?;
     let id = cuid2::create_id();
 
     // If icon provided, save it to the icons directory
@@ -341,29 +595,11 @@ async fn add_new_browser(
         std::fs::create_dir_all(&icons_dir)
             .map_err(|e| format!("Failed to create icons directory: {}", e))?;
 
-        // Determine file extension from MIME type
-        let ext = match mime.as_str() {
-            "image/png" => "png",
-            "image/jpeg" => "jpg",
-            "image/webp" => "webp",
-            "image/avif" => "avif",
-            _ => return Err(format!("Unsupported image format: {}", mime)),
-        };
-
-        // Decode base64 and save
-        use base64::Engine;
-        let image_bytes = base64::engine::general_purpose::STANDARD
-            .decode(icon_data)
-            .map_err(|e| format!("Failed to decode icon data: {}", e))?;
-
-        let icon_path = icons_dir.join(format!("{}.{}", id, ext));
-        std::fs::write(&icon_path, image_bytes)
-            .map_err(|e| format!("Failed to save icon: {}", e))?;
-
-        Some(ext.to_string())
+        let prepared = icons::prepare_upload(icon_data, mime)?;
+        Some(prepared.save(&icons_dir, &id)?)
     } else {
-        // No custom icon provided — try to use a bundled icon for known browsers
-        get_known_browser_asset(&name).and_then(|asset| copy_bundled_icon(&app_handle, asset, &id))
+        // No custom icon provided — try to use a bundled icon or the browser's own icon
+        detect_browser_icon(&app_handle, &name, &path, &id)
     };
 
     // Load config, add browser, and save
@@ -373,6 +609,10 @@ async fn add_new_browser(
         name,
         path,
         icon: icon_ext,
+        strip_tracking: strip_tracking.unwrap_or(true),
+        schemes,
+        hidden: false,
+        locked: false,
     });
     config.save(&app_handle)?;
 
@@ -382,7 +622,9 @@ async fn add_new_browser(
 #[tauri::command]
 async fn get_browser_icon(
     app_handle: tauri::AppHandle,
+    cache: tauri::State<'_, IconCache>,
     id: String,
+    size: Option<u32>, // Pixel size the icon is shown at, to pick the best stored size
 ) -> Result<Option<BrowserIcon>, String> {
     // Find the browser to get its icon extension
     let config = Config::load(&app_handle)?;
@@ -397,26 +639,19 @@ async fn get_browser_icon(
     };
 
     let icons_dir = get_icons_dir(&app_handle)?;
-    let icon_path = icons_dir.join(format!("{}.{}", id, ext));
+    let icon_path = icons::best_icon_path(&icons_dir, &id, ext, size);
 
     if !icon_path.exists() {
         return Ok(None);
     }
 
-    let image_bytes =
-        std::fs::read(&icon_path).map_err(|e| format!("Failed to read icon: {}", e))?;
-
-    use base64::Engine;
-    let base64_data = base64::engine::general_purpose::STANDARD.encode(&image_bytes);
+    let base64_data = cache.read_base64(&icon_path)?;
 
-    let mime_type = match ext.as_str() {
-        "png" => "image/png",
-        "jpg" => "image/jpeg",
-        "webp" => "image/webp",
-        "avif" => "image/avif",
-        _ => "application/octet-stream",
-    }
-    .to_string();
+    let ext = icon_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or(ext);
+    let mime_type = icons::mime_for_extension(ext).to_string();
 
     Ok(Some(BrowserIcon {
         data: base64_data,
@@ -424,6 +659,48 @@ async fn get_browser_icon(
     }))
 }
 
+/// Get the favicon of the website a URL (or bare host name) is on, fetching it if it isn't cached.
+/// Returns None if the site has no favicon.
+#[tauri::command]
+async fn get_favicon(
+    app_handle: tauri::AppHandle,
+    url: String,
+) -> Result<Option<BrowserIcon>, String> {
+    use tauri::Manager;
+    let cache_dir = app_handle
+        .path()
+        .app_cache_dir()
+        .map_err(|e| format!("Failed to get app cache directory: {}", e))?
+        .join("favicons");
+
+    // Fetching waits on network requests
+    let favicon =
+        tauri::async_runtime::spawn_blocking(move || favicons::get_favicon(&cache_dir, &url))
+            .await
+            .map_err(|e| format!("Failed to get favicon: {}", e))??;
+    let Some(favicon) = favicon else {
+        return Ok(None);
+    };
+
+    use base64::Engine;
+    Ok(Some(BrowserIcon {
+        data: base64::engine::general_purpose::STANDARD.encode(&favicon.data),
+        mime_type: favicon.format.mime_type().to_string(),
+    }))
+}
+
+/// Validate and lowercase a list of URL schemes, dropping duplicates.
+fn normalise_schemes(schemes: Vec<String>) -> Result<Vec<String>, String> {
+    let mut normalised: Vec<String> = Vec::new();
+    for scheme in schemes {
+        let scheme = schemes::normalise_scheme(&scheme)?;
+        if !normalised.contains(&scheme) {
+            normalised.push(scheme);
+        }
+    }
+    Ok(normalised)
+}
+
 fn get_icons_dir(app_handle: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
     use tauri::Manager;
     let app_data_dir = app_handle
@@ -446,15 +723,20 @@ async fn get_browser(app_handle: tauri::AppHandle, id: String) -> Result<Browser
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn update_browser(
     app_handle: tauri::AppHandle,
+    cache: tauri::State<'_, IconCache>,
     id: String,
     name: String,
     path: String,
     icon: Option<String>, // Base64-encoded image data (None = keep existing)
     icon_mime: Option<String>, // MIME type like "image/png"
     remove_icon: bool,    // If true, remove the existing icon
+    strip_tracking: Option<bool>, // None = keep existing
+    schemes: Option<Vec<String>>, // None = keep existing
 ) -> Result<(), String> {
+    let schemes = schemes.map(normalise_schemes).transpose()?;
     let mut config = Config::load(&app_handle)?;
 
     let browser_idx = config
@@ -462,15 +744,16 @@ async fn update_browser(
         .iter()
         .position(|b| b.id == id)
         .ok_or_else(|| format!("Browser with id '{}' not found", id))?;
+    ensure_browser_unlocked(&config.browsers[browser_idx])?;
 
     let icons_dir = get_icons_dir(&app_handle)?;
 
     // Handle icon changes
     let new_icon_ext = if remove_icon {
-        // Remove existing icon file if it exists
+        // Remove existing icon files if they exist
         if let Some(old_ext) = &config.browsers[browser_idx].icon {
-            let old_icon_path = icons_dir.join(format!("{}.{}", id, old_ext));
-            let _ = std::fs::remove_file(old_icon_path);
+            icons::remove_icon_files(&icons_dir, &id, old_ext)?;
+            cache.clear();
         }
         None
     } else if let (Some(icon_data), Some(mime)) = (&icon, &icon_mime) {
@@ -478,32 +761,17 @@ async fn update_browser(
         std::fs::create_dir_all(&icons_dir)
             .map_err(|e| format!("Failed to create icons directory: {}", e))?;
 
-        let ext = match mime.as_str() {
-            "image/png" => "png",
-            "image/jpeg" => "jpg",
-            "image/webp" => "webp",
-            "image/avif" => "avif",
-            _ => return Err(format!("Unsupported image format: {}", mime)),
-        };
+        // Check the upload before touching the old icon, so a bad one leaves it in place
+        let prepared = icons::prepare_upload(icon_data, mime)?;
 
-        // Remove old icon if extension differs
+        // Remove the old icon, including any smaller sizes
         if let Some(old_ext) = &config.browsers[browser_idx].icon {
-            if old_ext != ext {
-                let old_icon_path = icons_dir.join(format!("{}.{}", id, old_ext));
-                let _ = std::fs::remove_file(old_icon_path);
-            }
+            icons::remove_icon_files(&icons_dir, &id, old_ext)?;
         }
 
-        use base64::Engine;
-        let image_bytes = base64::engine::general_purpose::STANDARD
-            .decode(icon_data)
-            .map_err(|e| format!("Failed to decode icon data: {}", e))?;
-
-        let icon_path = icons_dir.join(format!("{}.{}", id, ext));
-        std::fs::write(&icon_path, image_bytes)
-            .map_err(|e| format!("Failed to save icon: {}", e))?;
-
-        Some(ext.to_string())
+        let ext = prepared.save(&icons_dir, &id)?;
+        cache.clear();
+        Some(ext)
     } else {
         // Keep existing icon
         config.browsers[browser_idx].icon.clone()
@@ -513,34 +781,187 @@ async fn update_browser(
     config.browsers[browser_idx].name = name;
     config.browsers[browser_idx].path = path;
     config.browsers[browser_idx].icon = new_icon_ext;
+    if let Some(strip_tracking) = strip_tracking {
+        config.browsers[browser_idx].strip_tracking = strip_tracking;
+    }
+    if let Some(schemes) = schemes {
+        config.browsers[browser_idx].schemes = schemes;
+    }
+
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+/// Reorder the browsers. `ids` must list every browser exactly once, in the new order.
+#[tauri::command]
+async fn reorder_browsers(app_handle: tauri::AppHandle, ids: Vec<String>) -> Result<(), String> {
+    let mut config = Config::load(&app_handle)?;
+
+    let mut reordered = Vec::with_capacity(ids.len());
+    for id in &ids {
+        let browser_idx = config
+            .browsers
+            .iter()
+            .position(|b| b.id == *id)
+            .ok_or_else(|| format!("Browser with id '{}' not found", id))?;
+        reordered.push(config.browsers.remove(browser_idx));
+    }
+    if let Some(missing) = config.browsers.first() {
+        return Err(format!(
+            "Browser with id '{}' is missing from the order",
+            missing.id
+        ));
+    }
+
+    config.browsers = reordered;
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+/// Hide a browser from the picker without deleting it, or show it again.
+#[tauri::command]
+async fn set_browser_hidden(
+    app_handle: tauri::AppHandle,
+    id: String,
+    hidden: bool,
+) -> Result<(), String> {
+    let mut config = Config::load(&app_handle)?;
+
+    let browser = config
+        .browsers
+        .iter_mut()
+        .find(|b| b.id == id)
+        .ok_or_else(|| format!("Browser with id '{}' not found", id))?;
+    ensure_browser_unlocked(browser)?;
 
+    browser.hidden = hidden;
     config.save(&app_handle)?;
 
     Ok(())
 }
 
+/// Get what opens URLs in a browser, so deleting it can warn about it.
+#[tauri::command]
+fn get_browser_usage(app_handle: tauri::AppHandle, id: String) -> Result<BrowserUsage, String> {
+    let config = Config::load(&app_handle)?;
+
+    let groups: Vec<&BrowserGroup> = config
+        .groups
+        .iter()
+        .filter(|g| g.browser_ids.contains(&id))
+        .collect();
+    let (rules, group_rules) = config
+        .rules
+        .iter()
+        .filter(|r| match rule_target(r) {
+            Some(RuleTarget::Browser(browser_id)) => browser_id == id,
+            Some(RuleTarget::Group(group_id)) => groups.iter().any(|g| g.id == group_id),
+            _ => false,
+        })
+        .cloned()
+        .partition(|r| r.group_id.is_empty());
+
+    Ok(BrowserUsage {
+        rules,
+        group_rules,
+        groups: groups.iter().map(|g| g.name.clone()).collect(),
+        fallback: config.fallback.browser_id == id,
+        override_active: snooze::active(&app_handle).is_some_and(|o| o.browser_id == id),
+    })
+}
+
+/// Delete a browser. Rules that open in it are handled according to `rules` (None = prompt to
+/// choose instead), and it is removed from groups, the fallback, rule suggestions and any
+/// temporary override.
 #[tauri::command]
-async fn delete_browser(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
+async fn delete_browser(
+    app_handle: tauri::AppHandle,
+    cache: tauri::State<'_, IconCache>,
+    id: String,
+    rules: Option<OnDeleteRules>,
+) -> Result<(), String> {
     let mut config = Config::load(&app_handle)?;
 
+    let browser = remove_browser(&mut config, &id, rules.unwrap_or_default())?;
+    config.save(&app_handle)?;
+    if snooze::active(&app_handle).is_some_and(|o| o.browser_id == id) {
+        snooze::clear(&app_handle)?;
+    }
+
+    // Remove icon files once the browser is gone; any left behind are cleaned up at startup
+    if let Some(ext) = &browser.icon {
+        let icons_dir = get_icons_dir(&app_handle)?;
+        icons::remove_icon_files(&icons_dir, &id, ext)?;
+        cache.clear();
+    }
+
+    Ok(())
+}
+
+/// Remove a browser from the config, along with everything that refers to it, handling the rules
+/// that open in it according to `rules`.
+fn remove_browser(config: &mut Config, id: &str, rules: OnDeleteRules) -> Result<Browser, String> {
     let browser_idx = config
         .browsers
         .iter()
         .position(|b| b.id == id)
         .ok_or_else(|| format!("Browser with id '{}' not found", id))?;
+    ensure_browser_unlocked(&config.browsers[browser_idx])?;
+    // Whether a rule opens in this browser, directly or as part of a group
+    let uses_browser = |rule: &Rule| match rule_target(rule) {
+        Some(RuleTarget::Browser(browser_id)) => browser_id == id,
+        Some(RuleTarget::Group(group_id)) => config
+            .groups
+            .iter()
+            .any(|g| g.id == group_id && g.browser_ids.iter().any(|b| b == id)),
+        _ => false,
+    };
+    if let Some(rule) = config.rules.iter().find(|r| r.locked && uses_browser(r)) {
+        return Err(format!(
+            "Browser '{}' is used by the rule '{}', which is set up by your administrator",
+            config.browsers[browser_idx].name, rule.pattern
+        ));
+    }
+    if let OnDeleteRules::Reassign(new_id) = &rules {
+        if new_id == id || !config.browsers.iter().any(|b| b.id == *new_id) {
+            return Err(format!("Browser with id '{}' not found", new_id));
+        }
+    }
 
-    // Remove icon file if it exists
-    if let Some(ext) = &config.browsers[browser_idx].icon {
-        let icons_dir = get_icons_dir(&app_handle)?;
-        let icon_path = icons_dir.join(format!("{}.{}", id, ext));
-        let _ = std::fs::remove_file(icon_path);
+    // Rules that open in an application or group only keep a stale reference, which is dropped;
+    // the rest open in this browser
+    for rule in config.rules.iter_mut().filter(|r| r.browser_id == id) {
+        if !matches!(rule_target(rule), Some(RuleTarget::Browser(_))) {
+            rule.browser_id.clear();
+        }
+    }
+    match rules {
+        OnDeleteRules::Prompt => {
+            for rule in config.rules.iter_mut().filter(|r| r.browser_id == id) {
+                rule.browser_id.clear();
+            }
+        }
+        OnDeleteRules::Remove => config.rules.retain(|r| r.browser_id != id),
+        OnDeleteRules::Reassign(new_id) => {
+            for rule in config.rules.iter_mut().filter(|r| r.browser_id == id) {
+                rule.browser_id = new_id.clone();
+            }
+        }
     }
 
-    // Remove browser from config
-    config.browsers.remove(browser_idx);
-    config.save(&app_handle)?;
+    // Remove browser from config, and everything else that refers to it
+    let browser = config.browsers.remove(browser_idx);
+    for group in &mut config.groups {
+        group.browser_ids.retain(|b| b != id);
+    }
+    if config.fallback.browser_id == id {
+        config.fallback.browser_id.clear();
+    }
+    config.suggestions.picks.retain(|p| p.browser_id != id);
 
-    Ok(())
+    Ok(browser)
 }
 
 #[tauri::command]
@@ -554,21 +975,32 @@ async fn add_rule(
     app_handle: tauri::AppHandle,
     pattern: String,
     browser_id: String,
+    strip_tracking: Option<bool>,
+    app_id: Option<String>,   // Application target to use instead of a browser
+    group_id: Option<String>, // Browser group to use instead of a browser
 ) -> Result<(), String> {
     // Validate the glob pattern by converting it
     glob_to_regex(&pattern)?;
 
     let mut config = Config::load(&app_handle)?;
+    let app_id = app_id.unwrap_or_default();
+    let group_id = group_id.unwrap_or_default();
 
     // Validate browser_id exists (empty means "prompt to choose")
     if !browser_id.is_empty() && !config.browsers.iter().any(|b| b.id == browser_id) {
         return Err(format!("Browser with id '{}' not found", browser_id));
     }
+    validate_app_id(&config, &app_id)?;
+    validate_group_id(&config, &group_id)?;
 
     config.rules.push(Rule {
         id: cuid2::create_id(),
         pattern,
         browser_id,
+        strip_tracking: strip_tracking.unwrap_or(true),
+        app_id,
+        group_id,
+        locked: false,
     });
     config.save(&app_handle)?;
 
@@ -576,11 +1008,15 @@ async fn add_rule(
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn update_rule(
     app_handle: tauri::AppHandle,
     id: String,
     pattern: String,
     browser_id: String,
+    strip_tracking: Option<bool>, // None = keep existing
+    app_id: Option<String>,       // None = keep existing, empty = no application target
+    group_id: Option<String>,     // None = keep existing, empty = no browser group target
 ) -> Result<(), String> {
     // Validate the glob pattern by converting it
     glob_to_regex(&pattern)?;
@@ -591,15 +1027,31 @@ async fn update_rule(
     if !browser_id.is_empty() && !config.browsers.iter().any(|b| b.id == browser_id) {
         return Err(format!("Browser with id '{}' not found", browser_id));
     }
+    if let Some(app_id) = &app_id {
+        validate_app_id(&config, app_id)?;
+    }
+    if let Some(group_id) = &group_id {
+        validate_group_id(&config, group_id)?;
+    }
 
     let rule = config
         .rules
         .iter_mut()
         .find(|r| r.id == id)
         .ok_or_else(|| format!("Rule with id '{}' not found", id))?;
+    ensure_rule_unlocked(rule)?;
 
     rule.pattern = pattern;
     rule.browser_id = browser_id;
+    if let Some(app_id) = app_id {
+        rule.app_id = app_id;
+    }
+    if let Some(group_id) = group_id {
+        rule.group_id = group_id;
+    }
+    if let Some(strip_tracking) = strip_tracking {
+        rule.strip_tracking = strip_tracking;
+    }
     config.save(&app_handle)?;
 
     Ok(())
@@ -614,6 +1066,7 @@ async fn delete_rule(app_handle: tauri::AppHandle, id: String) -> Result<(), Str
         .iter()
         .position(|r| r.id == id)
         .ok_or_else(|| format!("Rule with id '{}' not found", id))?;
+    ensure_rule_unlocked(&config.rules[rule_idx])?;
 
     config.rules.remove(rule_idx);
     config.save(&app_handle)?;
@@ -621,88 +1074,1034 @@ async fn delete_rule(app_handle: tauri::AppHandle, id: String) -> Result<(), Str
     Ok(())
 }
 
-/// Convert a glob pattern (where `*` matches anything) into a regex pattern.
-/// All characters except `*` are treated as literals.
-fn glob_to_regex(pattern: &str) -> Result<regex::Regex, String> {
-    let mut regex_str = String::from("(?i)^");
-    for part in pattern.split('*') {
-        regex_str.push_str(&regex::escape(part));
-        regex_str.push_str(".*");
+/// Refuse to change a browser from the system config, which only an administrator can change.
+fn ensure_browser_unlocked(browser: &Browser) -> Result<(), String> {
+    if browser.locked {
+        return Err(format!(
+            "Browser '{}' is set up by your administrator, so can't be changed",
+            browser.name
+        ));
     }
-    // Remove the trailing `.*` added after the last split part
-    regex_str.truncate(regex_str.len() - 2);
-    regex_str.push('$');
-    regex::Regex::new(&regex_str).map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))
+    Ok(())
+}
+
+/// Refuse to change a rule from the system config, which only an administrator can change.
+fn ensure_rule_unlocked(rule: &Rule) -> Result<(), String> {
+    if rule.locked {
+        return Err(format!(
+            "Rule '{}' is set up by your administrator, so can't be changed",
+            rule.pattern
+        ));
+    }
+    Ok(())
 }
 
-/// Check if a URL matches any rule. Returns the browser_id of the first matching rule, or null.
-/// An empty browser_id means "prompt to choose" — return null to let the user pick.
+/// Find rules that are duplicated, shadowed by an earlier broader rule, or overlap an earlier rule
+/// with a different target.
 #[tauri::command]
-fn check_rules(app_handle: tauri::AppHandle, url: String) -> Result<Option<String>, String> {
+fn analyze_rules(app_handle: tauri::AppHandle) -> Result<Vec<RuleWarning>, String> {
     let config = Config::load(&app_handle)?;
+    Ok(conflicts::analyze(&config))
+}
 
-    for rule in &config.rules {
-        let re = glob_to_regex(&rule.pattern)?;
-        if re.is_match(&url) {
-            // Empty browser_id means "prompt to choose" — stop checking further rules
-            if rule.browser_id.is_empty() {
-                return Ok(None);
-            }
-            // Verify the browser still exists
-            if config.browsers.iter().any(|b| b.id == rule.browser_id) {
-                return Ok(Some(rule.browser_id.clone()));
-            }
-        }
+/// Check that an application target id exists (empty means "no application").
+fn validate_app_id(config: &Config, app_id: &str) -> Result<(), String> {
+    if !app_id.is_empty() && !config.apps.iter().any(|a| a.id == app_id) {
+        return Err(format!("Application with id '{}' not found", app_id));
     }
+    Ok(())
+}
+
+#[tauri::command]
+fn get_apps(app_handle: tauri::AppHandle) -> Result<Vec<AppTarget>, String> {
+    let config = Config::load(&app_handle)?;
+    Ok(config.apps)
+}
 
-    Ok(None)
+/// Check that an application target's URL pattern is a valid regex.
+fn validate_app_pattern(pattern: &str) -> Result<(), String> {
+    regex::Regex::new(pattern)
+        .map(|_| ())
+        .map_err(|e| format!("Invalid application pattern '{}': {}", pattern, e))
 }
 
 #[tauri::command]
-async fn exit_app(app_handle: tauri::AppHandle) {
-    app_handle.exit(0);
+async fn add_app(
+    app_handle: tauri::AppHandle,
+    name: String,
+    pattern: String,
+    template: String,
+    command: String,
+) -> Result<(), String> {
+    validate_app_pattern(&pattern)?;
+
+    let mut config = Config::load(&app_handle)?;
+    config.apps.push(AppTarget {
+        id: cuid2::create_id(),
+        name,
+        pattern,
+        template,
+        command,
+    });
+    config.save(&app_handle)?;
+
+    Ok(())
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_opener::init())
-        .manage(OpenedUrl(Mutex::new(None)))
-        .invoke_handler(tauri::generate_handler![
-            get_browsers,
-            get_browser,
-            is_default_browser,
-            make_default_browser,
-            url_to_open,
-            open_url_in_browser,
-            open_config_in_vscode,
-            add_new_browser,
-            update_browser,
-            delete_browser,
-            get_browser_icon,
-            get_rules,
-            add_rule,
-            update_rule,
-            delete_rule,
-            check_rules,
-            exit_app
-        ])
-        .build(tauri::generate_context!())
-        .expect("error while building tauri application")
-        .run(|_app, _event| {
-            #[cfg(target_os = "macos")]
-            {
-                use tauri::Manager;
-                if let tauri::RunEvent::Opened { urls } = _event {
-                    if let Some(url) = urls.first() {
-                        let url_str = url.to_string();
-                        if let Some(state) = _app.try_state::<OpenedUrl>() {
-                            *state.0.lock().unwrap() = Some(url_str.clone());
-                        }
-                        use tauri::Emitter;
-                        let _ = _app.emit("url-opened", url_str);
-                    }
-                }
-            }
-        });
+#[tauri::command]
+async fn update_app(
+    app_handle: tauri::AppHandle,
+    id: String,
+    name: String,
+    pattern: String,
+    template: String,
+    command: String,
+) -> Result<(), String> {
+    validate_app_pattern(&pattern)?;
+
+    let mut config = Config::load(&app_handle)?;
+
+    let app = config
+        .apps
+        .iter_mut()
+        .find(|a| a.id == id)
+        .ok_or_else(|| format!("Application with id '{}' not found", id))?;
+
+    app.name = name;
+    app.pattern = pattern;
+    app.template = template;
+    app.command = command;
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+/// Delete an application target. Rules that pointed to it fall back to their browser.
+#[tauri::command]
+async fn delete_app(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
+    let mut config = Config::load(&app_handle)?;
+
+    let app_idx = config
+        .apps
+        .iter()
+        .position(|a| a.id == id)
+        .ok_or_else(|| format!("Application with id '{}' not found", id))?;
+
+    config.apps.remove(app_idx);
+    for rule in config.rules.iter_mut().filter(|r| r.app_id == id) {
+        rule.app_id.clear();
+    }
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+/// Check that a browser group id exists (empty means "no group").
+fn validate_group_id(config: &Config, group_id: &str) -> Result<(), String> {
+    if !group_id.is_empty() && !config.groups.iter().any(|g| g.id == group_id) {
+        return Err(format!("Browser group with id '{}' not found", group_id));
+    }
+    Ok(())
+}
+
+/// Check that every browser in a group exists.
+fn validate_group_browsers(config: &Config, browser_ids: &[String]) -> Result<(), String> {
+    for id in browser_ids {
+        if !config.browsers.iter().any(|b| b.id == *id) {
+            return Err(format!("Browser with id '{}' not found", id));
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn get_groups(app_handle: tauri::AppHandle) -> Result<Vec<BrowserGroup>, String> {
+    let config = Config::load(&app_handle)?;
+    Ok(config.groups)
+}
+
+#[tauri::command]
+async fn add_group(
+    app_handle: tauri::AppHandle,
+    name: String,
+    browser_ids: Vec<String>,
+) -> Result<(), String> {
+    let mut config = Config::load(&app_handle)?;
+    validate_group_browsers(&config, &browser_ids)?;
+
+    config.groups.push(BrowserGroup {
+        id: cuid2::create_id(),
+        name,
+        browser_ids,
+    });
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn update_group(
+    app_handle: tauri::AppHandle,
+    id: String,
+    name: String,
+    browser_ids: Vec<String>,
+) -> Result<(), String> {
+    let mut config = Config::load(&app_handle)?;
+    validate_group_browsers(&config, &browser_ids)?;
+
+    let group = config
+        .groups
+        .iter_mut()
+        .find(|g| g.id == id)
+        .ok_or_else(|| format!("Browser group with id '{}' not found", id))?;
+
+    group.name = name;
+    group.browser_ids = browser_ids;
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn delete_group(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
+    let mut config = Config::load(&app_handle)?;
+
+    let group_idx = config
+        .groups
+        .iter()
+        .position(|g| g.id == id)
+        .ok_or_else(|| format!("Browser group with id '{}' not found", id))?;
+
+    config.groups.remove(group_idx);
+    for rule in config.rules.iter_mut().filter(|r| r.group_id == id) {
+        rule.group_id.clear();
+    }
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+fn get_rewrites(app_handle: tauri::AppHandle) -> Result<Vec<RewriteRule>, String> {
+    let config = Config::load(&app_handle)?;
+    Ok(config.rewrites)
+}
+
+#[tauri::command]
+async fn add_rewrite(
+    app_handle: tauri::AppHandle,
+    pattern: String,
+    replacement: String,
+) -> Result<(), String> {
+    pipeline::validate_rewrite(&pattern, &replacement)?;
+
+    let mut config = Config::load(&app_handle)?;
+    config.rewrites.push(RewriteRule {
+        id: cuid2::create_id(),
+        pattern,
+        replacement,
+    });
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn update_rewrite(
+    app_handle: tauri::AppHandle,
+    id: String,
+    pattern: String,
+    replacement: String,
+) -> Result<(), String> {
+    pipeline::validate_rewrite(&pattern, &replacement)?;
+
+    let mut config = Config::load(&app_handle)?;
+
+    let rewrite = config
+        .rewrites
+        .iter_mut()
+        .find(|r| r.id == id)
+        .ok_or_else(|| format!("Rewrite with id '{}' not found", id))?;
+
+    rewrite.pattern = pattern;
+    rewrite.replacement = replacement;
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn delete_rewrite(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
+    let mut config = Config::load(&app_handle)?;
+
+    let rewrite_idx = config
+        .rewrites
+        .iter()
+        .position(|r| r.id == id)
+        .ok_or_else(|| format!("Rewrite with id '{}' not found", id))?;
+
+    config.rewrites.remove(rewrite_idx);
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+/// Convert a glob pattern (where `*` matches anything) into a regex pattern.
+/// All characters except `*` are treated as literals.
+fn glob_to_regex(pattern: &str) -> Result<regex::Regex, String> {
+    let mut regex_str = String::from("(?i)^");
+    for part in pattern.split('*') {
+        regex_str.push_str(&regex::escape(part));
+        regex_str.push_str(".*");
+    }
+    // Remove the trailing `.*` added after the last split part
+    regex_str.truncate(regex_str.len() - 2);
+    regex_str.push('$');
+    regex::Regex::new(&regex_str).map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))
+}
+
+/// Run a URL through the pipeline (unwrap redirect wrappers, expand short links, then apply
+/// rewrite rules), returning the URL that should actually be opened.
+#[tauri::command]
+async fn prepare_url(app_handle: tauri::AppHandle, url: String) -> Result<String, String> {
+    let config = Config::load(&app_handle)?;
+    Ok(run_pipeline(url, config).await?.url)
+}
+
+/// Show what the pipeline would do to a URL, step by step, without opening anything.
+#[tauri::command]
+async fn dry_run_url(app_handle: tauri::AppHandle, url: String) -> Result<PipelineResult, String> {
+    let config = Config::load(&app_handle)?;
+    run_pipeline(url, config).await
+}
+
+/// Run the pipeline on a blocking thread, as expanding short links waits on network requests.
+async fn run_pipeline(url: String, config: Config) -> Result<PipelineResult, String> {
+    tauri::async_runtime::spawn_blocking(move || pipeline::run(&url, &config))
+        .await
+        .map_err(|e| format!("Failed to prepare URL: {}", e))
+}
+
+/// Check if a URL matches any rule. Returns the target (browser, application or group) of the first
+/// matching rule, or null. A rule with no target means "prompt to choose" — return null to let
+/// the user pick. The URL must already have been through the pipeline (see `prepare_url`), so
+/// short links aren't expanded a second time. An active temporary override is consulted before
+/// the user's own rules, but after the locked rules from the system config, which it can't skip.
+/// Rules whose pattern doesn't parse are passed over, as `explain_url` shows.
+#[tauri::command]
+async fn check_rules(
+    app_handle: tauri::AppHandle,
+    url: String,
+) -> Result<Option<RuleTarget>, String> {
+    let config = Config::load(&app_handle)?;
+    Ok(route(&config, &url, snooze::active(&app_handle)))
+}
+
+/// Where a URL opens, given the active override, if any (None = prompt to choose).
+fn route(config: &Config, url: &str, active: Option<TemporaryOverride>) -> Option<RuleTarget> {
+    let (locked, unlocked): (Vec<&Rule>, Vec<&Rule>) = config.rules.iter().partition(|r| r.locked);
+
+    if let Some(rule) = find_matching_rule(config, locked, url) {
+        return rule_target(rule);
+    }
+
+    // A temporary override takes precedence over the user's own rules
+    if let Some(active) = active {
+        if active.browser_id.is_empty() {
+            return None;
+        }
+        if config.browsers.iter().any(|b| b.id == active.browser_id) {
+            return Some(RuleTarget::Browser(active.browser_id));
+        }
+    }
+
+    find_matching_rule(config, unlocked, url).and_then(rule_target)
+}
+
+/// Explain where a URL opens and why: the URL after the pipeline, each rule with whether and why
+/// it matched, the rule that decided, and why later rules weren't checked.
+#[tauri::command]
+async fn explain_url(app_handle: tauri::AppHandle, url: String) -> Result<Explanation, String> {
+    let config = Config::load(&app_handle)?;
+    let active = snooze::active(&app_handle);
+    // Explaining runs the pipeline, which may expand short links
+    tauri::async_runtime::spawn_blocking(move || explain::explain(&config, &url, active))
+        .await
+        .map_err(|e| format!("Failed to explain URL: {}", e))
+}
+
+/// Get where a rule sends matching URLs, or None if it prompts to choose.
+fn rule_target(rule: &Rule) -> Option<RuleTarget> {
+    if !rule.app_id.is_empty() {
+        Some(RuleTarget::App(rule.app_id.clone()))
+    } else if !rule.group_id.is_empty() {
+        Some(RuleTarget::Group(rule.group_id.clone()))
+    } else if !rule.browser_id.is_empty() {
+        Some(RuleTarget::Browser(rule.browser_id.clone()))
+    } else {
+        None
+    }
+}
+
+/// Find the rule that decides where a URL opens: the first of `rules` that matches and either
+/// prompts to choose or points to a target that can still open the URL. A rule with an invalid
+/// pattern counts as no match.
+fn find_matching_rule<'a>(config: &Config, rules: Vec<&'a Rule>, url: &str) -> Option<&'a Rule> {
+    rules
+        .into_iter()
+        .find(|rule| matches!(check_rule(config, rule, url), Ok(RuleCheck::Applies)))
+}
+
+/// How a single rule applies to a URL.
+enum RuleCheck {
+    NoMatch,
+    Unusable(String), // The pattern matches, but the target can't open the URL (the reason why)
+    Applies,
+}
+
+fn check_rule(config: &Config, rule: &Rule, url: &str) -> Result<RuleCheck, String> {
+    let re = glob_to_regex(&rule.pattern)?;
+    if !re.is_match(url) {
+        return Ok(RuleCheck::NoMatch);
+    }
+
+    // Application targets must exist and be able to transform this URL
+    if !rule.app_id.is_empty() {
+        let Some(app) = config.apps.iter().find(|a| a.id == rule.app_id) else {
+            return Ok(RuleCheck::Unusable(format!(
+                "Application with id '{}' not found",
+                rule.app_id
+            )));
+        };
+        if apps::transform_url(url, app).is_none() {
+            return Ok(RuleCheck::Unusable(format!(
+                "URL can't be opened in application '{}'",
+                app.name
+            )));
+        }
+        return Ok(RuleCheck::Applies);
+    }
+    // Group targets must exist and contain at least one browser
+    if !rule.group_id.is_empty() {
+        let Some(group) = config.groups.iter().find(|g| g.id == rule.group_id) else {
+            return Ok(RuleCheck::Unusable(format!(
+                "Browser group with id '{}' not found",
+                rule.group_id
+            )));
+        };
+        if group.browser_ids.is_empty() {
+            return Ok(RuleCheck::Unusable(format!(
+                "Browser group '{}' has no browsers",
+                group.name
+            )));
+        }
+        return Ok(RuleCheck::Applies);
+    }
+    // Empty browser_id means "prompt to choose" — stop checking further rules.
+    // Otherwise, verify the browser still exists.
+    if !rule.browser_id.is_empty() && !config.browsers.iter().any(|b| b.id == rule.browser_id) {
+        return Ok(RuleCheck::Unusable(format!(
+            "Browser with id '{}' not found",
+            rule.browser_id
+        )));
+    }
+    Ok(RuleCheck::Applies)
+}
+
+#[tauri::command]
+fn get_rule_suggestions(app_handle: tauri::AppHandle) -> Result<Vec<RuleSuggestion>, String> {
+    let config = Config::load(&app_handle)?;
+    Ok(suggestions::get_suggestions(&config))
+}
+
+#[tauri::command]
+async fn accept_rule_suggestion(
+    app_handle: tauri::AppHandle,
+    origin: String,
+) -> Result<(), String> {
+    let mut config = Config::load(&app_handle)?;
+    suggestions::accept(&mut config, &origin)?;
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+/// Dismiss a rule suggestion. If `never` is set, the origin is never suggested again.
+#[tauri::command]
+async fn dismiss_rule_suggestion(
+    app_handle: tauri::AppHandle,
+    origin: String,
+    never: bool,
+) -> Result<(), String> {
+    let mut config = Config::load(&app_handle)?;
+    suggestions::dismiss(&mut config, &origin, never);
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+/// Get the extra URL schemes (beyond http and https) Pick Browser registers for.
+#[tauri::command]
+fn get_schemes(app_handle: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let config = Config::load(&app_handle)?;
+    Ok(config.schemes)
+}
+
+/// Add an extra URL scheme to register for. Takes effect the next time Pick Browser is made
+/// the default browser.
+#[tauri::command]
+async fn add_scheme(app_handle: tauri::AppHandle, scheme: String) -> Result<(), String> {
+    let scheme = schemes::normalise_scheme(&scheme)?;
+    if schemes::WEB_SCHEMES.contains(&scheme.as_str()) {
+        return Err(format!("'{}' is always registered", scheme));
+    }
+    schemes::check_registrable(&scheme)?;
+
+    let mut config = Config::load(&app_handle)?;
+    if !config.schemes.contains(&scheme) {
+        config.schemes.push(scheme);
+        config.save(&app_handle)?;
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn remove_scheme(app_handle: tauri::AppHandle, scheme: String) -> Result<(), String> {
+    let scheme = schemes::normalise_scheme(&scheme)?;
+
+    let mut config = Config::load(&app_handle)?;
+    let scheme_idx = config
+        .schemes
+        .iter()
+        .position(|s| *s == scheme)
+        .ok_or_else(|| format!("Scheme '{}' is not registered", scheme))?;
+
+    config.schemes.remove(scheme_idx);
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+fn get_fallback_settings(app_handle: tauri::AppHandle) -> Result<FallbackSettings, String> {
+    let config = Config::load(&app_handle)?;
+    Ok(config.fallback)
+}
+
+/// Set the default browser (empty for none) and how many seconds the picker waits before
+/// opening a URL in it (0 to never open automatically).
+#[tauri::command]
+async fn set_fallback_settings(
+    app_handle: tauri::AppHandle,
+    browser_id: String,
+    countdown_secs: u32,
+) -> Result<(), String> {
+    let mut config = Config::load(&app_handle)?;
+
+    if !browser_id.is_empty() && !config.browsers.iter().any(|b| b.id == browser_id) {
+        return Err(format!("Browser with id '{}' not found", browser_id));
+    }
+
+    config.fallback = FallbackSettings {
+        browser_id,
+        countdown_secs,
+    };
+    config.save(&app_handle)?;
+
+    Ok(())
+}
+
+/// Start the auto-open countdown for a URL nothing else has handled. The backend owns the timer
+/// and emits `countdown-tick` / `countdown-finished` events. Returns null if there's nothing to
+/// count down to.
+#[tauri::command]
+async fn start_countdown(
+    app_handle: tauri::AppHandle,
+    url: String,
+    close: bool,
+) -> Result<Option<CountdownTick>, String> {
+    countdown::start(&app_handle, url, close)
+}
+
+#[tauri::command]
+async fn cancel_countdown(app_handle: tauri::AppHandle) {
+    countdown::cancel(&app_handle);
+}
+
+/// Get the active temporary override, or null if the rules apply as normal.
+#[tauri::command]
+fn get_override(app_handle: tauri::AppHandle) -> Option<TemporaryOverride> {
+    snooze::active(&app_handle)
+}
+
+/// Temporarily override the rules for the next `minutes`: open everything in `browser_id`,
+/// or always prompt to choose if it's empty.
+#[tauri::command]
+async fn set_override(
+    app_handle: tauri::AppHandle,
+    browser_id: String,
+    minutes: u32,
+) -> Result<TemporaryOverride, String> {
+    if minutes == 0 {
+        return Err("Override duration must be at least a minute".to_string());
+    }
+
+    let config = Config::load(&app_handle)?;
+    if !browser_id.is_empty() && !config.browsers.iter().any(|b| b.id == browser_id) {
+        return Err(format!("Browser with id '{}' not found", browser_id));
+    }
+
+    snooze::set(&app_handle, browser_id, minutes)
+}
+
+#[tauri::command]
+async fn clear_override(app_handle: tauri::AppHandle) -> Result<(), String> {
+    snooze::clear(&app_handle)
+}
+
+/// Check the config for dangling references, duplicate ids, missing icon files and unparsable
+/// patterns.
+#[tauri::command]
+fn validate_config(app_handle: tauri::AppHandle) -> Result<Vec<ConfigIssue>, String> {
+    let config = Config::load(&app_handle)?;
+    let config_path = Config::get_config_path(&app_handle)?;
+    let config_dir = config_path.parent().unwrap_or(std::path::Path::new("."));
+    let icons_dir = get_icons_dir(&app_handle)?;
+    Ok(validate::validate(&config, config_dir, &icons_dir))
+}
+
+/// Remove icon files no browser uses and repair browsers whose icon file is missing, by detecting
+/// their icon again or, failing that, dropping it. Also run at startup.
+#[tauri::command]
+fn clean_up_icons(
+    app_handle: tauri::AppHandle,
+    cache: tauri::State<'_, IconCache>,
+) -> Result<IconCleanup, String> {
+    let mut config = Config::load(&app_handle)?;
+    let icons_dir = get_icons_dir(&app_handle)?;
+    let cleanup = icons::clean_up(&mut config, &icons_dir)?;
+
+    for id in &cleanup.repaired_browsers {
+        if let Some(browser) = config.browsers.iter_mut().find(|b| b.id == *id) {
+            browser.icon = detect_browser_icon(&app_handle, &browser.name, &browser.path, id);
+        }
+    }
+    if !cleanup.repaired_browsers.is_empty() {
+        config.save(&app_handle)?;
+    }
+    if !cleanup.repaired_browsers.is_empty() || !cleanup.removed_files.is_empty() {
+        cache.clear();
+    }
+
+    Ok(cleanup)
+}
+
+/// Export the config and browser icons as a single file, to `path` or, by default, a new file in
+/// the downloads folder. Returns the path written to.
+#[tauri::command]
+fn export_config(app_handle: tauri::AppHandle, path: Option<String>) -> Result<String, String> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use tauri::Manager;
+
+    let exported_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let path = match path {
+        Some(path) => std::path::PathBuf::from(path),
+        None => app_handle
+            .path()
+            .download_dir()
+            .map_err(|e| format!("Failed to get downloads directory: {}", e))?
+            .join(format!("pick-browser-export-{}.json", exported_at)),
+    };
+
+    let config = Config::load(&app_handle)?;
+    let icons_dir = get_icons_dir(&app_handle)?;
+    // Entries from the system config come with the machine, so aren't exported
+    let bundle = bundle::export(config.user_layer(), &icons_dir, exported_at)?;
+    let content = serde_json::to_string_pretty(&bundle)
+        .map_err(|e| format!("Failed to serialize export: {}", e))?;
+    std::fs::write(&path, content).map_err(|e| format!("Failed to write export file: {}", e))?;
+
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Import a file made by `export_config`, merging it into the config or replacing it. Browsers
+/// whose executable isn't on this machine can be given a new path in `path_remaps`, keyed by their
+/// id in the export. With `dry_run`, nothing is changed and the report says what would be.
+#[tauri::command]
+fn import_config(
+    app_handle: tauri::AppHandle,
+    cache: tauri::State<'_, IconCache>,
+    content: String,
+    mode: Option<ImportMode>,
+    path_remaps: Option<HashMap<String, String>>,
+    dry_run: Option<bool>,
+) -> Result<ImportReport, String> {
+    let bundle = bundle::parse(&content)?;
+    let local = Config::load(&app_handle)?;
+    let import = bundle::import(
+        local,
+        bundle,
+        mode.unwrap_or_default(),
+        &path_remaps.unwrap_or_default(),
+    );
+    if dry_run.unwrap_or(false) {
+        return Ok(import.report);
+    }
+
+    let mut config = import.config;
+    let mut report = import.report;
+    let icons_dir = get_icons_dir(&app_handle)?;
+    std::fs::create_dir_all(&icons_dir)
+        .map_err(|e| format!("Failed to create icons directory: {}", e))?;
+    for (id, prepared) in import.icons {
+        let Some(browser) = config.browsers.iter_mut().find(|b| b.id == id) else {
+            continue;
+        };
+        // Sizes left from an icon the browser had here would otherwise be picked over the new one
+        icons::remove_icon_files(&icons_dir, &id, "png")?;
+        match prepared.save(&icons_dir, &id) {
+            Ok(ext) => browser.icon = Some(ext),
+            Err(e) => report
+                .skipped
+                .push(format!("Icon of browser '{}': {}", browser.name, e)),
+        }
+    }
+    config.save(&app_handle)?;
+
+    // Replacing can leave icons of browsers that are no longer set up
+    icons::clean_up(&mut config, &icons_dir)?;
+    cache.clear();
+
+    Ok(report)
+}
+
+/// Import the browsers and rules of another browser picker, from its config file. With
+/// `dry_run`, nothing is changed and the report says what would be.
+#[tauri::command]
+fn import_rules(
+    app_handle: tauri::AppHandle,
+    source: migrate::Source,
+    content: String,
+    dry_run: Option<bool>,
+) -> Result<MigrationReport, String> {
+    let foreign = migrate::parse(source, &content)?;
+    let mut config = Config::load(&app_handle)?;
+    let existing: Vec<String> = config.browsers.iter().map(|b| b.id.clone()).collect();
+    let report = migrate::apply(&mut config, foreign);
+    if dry_run.unwrap_or(false) {
+        return Ok(report);
+    }
+
+    for browser in config
+        .browsers
+        .iter_mut()
+        .filter(|b| !existing.contains(&b.id))
+    {
+        browser.icon = detect_browser_icon(&app_handle, &browser.name, &browser.path, &browser.id);
+    }
+    config.save(&app_handle)?;
+
+    Ok(report)
+}
+
+/// Rewrite the config file in another format, e.g. TOML to edit it by hand with comments. Returns
+/// the new file's path.
+#[tauri::command]
+fn convert_config(app_handle: tauri::AppHandle, format: ConfigFormat) -> Result<String, String> {
+    let path = Config::convert(&app_handle, format)?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+#[tauri::command]
+async fn exit_app(app_handle: tauri::AppHandle) {
+    app_handle.exit(0);
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .manage(OpenedUrl(Mutex::new(None)))
+        .manage(Countdown::default())
+        .manage(IconCache::default())
+        .manage(BrowserCache::default())
+        .register_uri_scheme_protocol("pickicon", |ctx, request| {
+            icon_protocol::handle(ctx.app_handle(), &request)
+        })
+        .setup(|app| {
+            use tauri::Manager;
+            app.manage(OverrideState::load(app.handle()));
+
+            // `--explain <url>` prints where the URL would open, and why, instead of opening it
+            let args: Vec<String> = std::env::args().collect();
+            if let Some(pos) = args.iter().position(|a| a == "--explain") {
+                let Some(url) = args.get(pos + 1) else {
+                    eprintln!("Usage: {} --explain <url>", args[0]);
+                    std::process::exit(2);
+                };
+                let explanation = Config::load(app.handle())
+                    .map(|config| explain::explain(&config, url, snooze::active(app.handle())));
+                match explanation
+                    .and_then(|e| serde_json::to_string_pretty(&e).map_err(|e| e.to_string()))
+                {
+                    Ok(json) => println!("{}", json),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                }
+                std::process::exit(0);
+            }
+
+            if let Err(e) = clean_up_icons(app.handle().clone(), app.state()) {
+                eprintln!("Failed to clean up icons: {}", e);
+            }
+
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            get_browsers,
+            get_browser,
+            is_default_browser,
+            make_default_browser,
+            url_to_open,
+            open_url_in_browser,
+            open_url_in_browsers,
+            open_url_in_app,
+            open_config_in_vscode,
+            add_new_browser,
+            update_browser,
+            reorder_browsers,
+            set_browser_hidden,
+            get_browser_usage,
+            delete_browser,
+            get_browser_icon,
+            get_favicon,
+            get_rules,
+            add_rule,
+            update_rule,
+            delete_rule,
+            analyze_rules,
+            check_rules,
+            explain_url,
+            prepare_url,
+            dry_run_url,
+            get_apps,
+            add_app,
+            update_app,
+            delete_app,
+            get_groups,
+            add_group,
+            update_group,
+            delete_group,
+            get_rewrites,
+            add_rewrite,
+            update_rewrite,
+            delete_rewrite,
+            get_rule_suggestions,
+            accept_rule_suggestion,
+            dismiss_rule_suggestion,
+            get_schemes,
+            add_scheme,
+            remove_scheme,
+            get_fallback_settings,
+            set_fallback_settings,
+            start_countdown,
+            cancel_countdown,
+            get_override,
+            set_override,
+            clear_override,
+            validate_config,
+            clean_up_icons,
+            export_config,
+            import_config,
+            import_rules,
+            convert_config,
+            exit_app
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, _event| {
+            #[cfg(target_os = "macos")]
+            {
+                use tauri::Manager;
+                if let tauri::RunEvent::Opened { urls } = _event {
+                    if let Some(url) = urls.first() {
+                        let url_str = url.to_string();
+                        // url_to_open resolves link files itself, reporting any that can't be read
+                        if let Some(state) = _app.try_state::<OpenedUrl>() {
+                            *state.0.lock().unwrap() = Some(url_str.clone());
+                        }
+                        if let Ok(resolved) = link_files::resolve_file(&url_str) {
+                            use tauri::Emitter;
+                            let _ = _app.emit("url-opened", resolved.unwrap_or(url_str));
+                        }
+                    }
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_support::{self, invalid_pattern, rule};
+
+    fn config() -> Config {
+        let mut config = test_support::config();
+        config.rules = vec![
+            rule("r1", "*://docs.example.com/*", "ff"),
+            rule("r2", "*://mail.example.com/*", "work"),
+            rule("r3", "*://example.com/*", "ff"),
+        ];
+        config.groups = vec![BrowserGroup {
+            id: "g1".to_string(),
+            name: "All".to_string(),
+            browser_ids: vec!["ff".to_string(), "work".to_string()],
+        }];
+        config.fallback.browser_id = "ff".to_string();
+        config
+    }
+
+    fn targets(config: &Config) -> Vec<(&str, &str)> {
+        config
+            .rules
+            .iter()
+            .map(|r| (r.id.as_str(), r.browser_id.as_str()))
+            .collect()
+    }
+
+    fn routed(config: &Config, url: &str, active: Option<&str>) -> Option<String> {
+        let active = active.map(|browser_id| TemporaryOverride {
+            browser_id: browser_id.to_string(),
+            expires_at: u64::MAX,
+        });
+        match route(config, url, active) {
+            Some(RuleTarget::Browser(id)) => Some(id),
+            other => other.map(|target| format!("{:?}", target)),
+        }
+    }
+
+    #[test]
+    fn routing_passes_over_invalid_patterns_as_explain_does() {
+        let mut config = config();
+        config.rules[0].pattern = invalid_pattern();
+        config.rules.push(rule("all", "*", "work"));
+        let url = "https://docs.example.com/";
+
+        assert_eq!(routed(&config, url, None).as_deref(), Some("work"));
+        let explanation = explain::explain(&config, url, None);
+        assert_eq!(explanation.winner.as_deref(), Some("all"));
+    }
+
+    #[test]
+    fn routing_checks_locked_rules_then_the_override() {
+        let mut config = config();
+        config.rules[1].locked = true;
+
+        let mail = "https://mail.example.com/";
+        assert_eq!(routed(&config, mail, Some("ff")).as_deref(), Some("work"));
+        let docs = "https://docs.example.com/";
+        assert_eq!(routed(&config, docs, Some("work")).as_deref(), Some("work"));
+        assert_eq!(routed(&config, docs, Some("")), None);
+        assert_eq!(routed(&config, docs, Some("gone")).as_deref(), Some("ff"));
+    }
+
+    fn on_delete(value: serde_json::Value) -> OnDeleteRules {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn deleting_a_browser_can_prompt_for_its_rules() {
+        let mut config = config();
+        let rules = on_delete(serde_json::json!({"action": "prompt"}));
+        let browser = remove_browser(&mut config, "ff", rules).unwrap();
+
+        assert_eq!(browser.id, "ff");
+        assert_eq!(targets(&config), [("r1", ""), ("r2", "work"), ("r3", "")]);
+        assert_eq!(config.groups[0].browser_ids, ["work"]);
+        assert!(config.fallback.browser_id.is_empty());
+    }
+
+    #[test]
+    fn deleting_a_browser_can_remove_its_rules() {
+        let mut config = config();
+        let rules = on_delete(serde_json::json!({"action": "remove"}));
+        remove_browser(&mut config, "ff", rules).unwrap();
+
+        assert_eq!(targets(&config), [("r2", "work")]);
+    }
+
+    #[test]
+    fn deleting_a_browser_can_reassign_its_rules() {
+        let mut config = config();
+        let rules = on_delete(serde_json::json!({"action": "reassign", "browser_id": "work"}));
+        remove_browser(&mut config, "ff", rules).unwrap();
+
+        assert_eq!(
+            targets(&config),
+            [("r1", "work"), ("r2", "work"), ("r3", "work")]
+        );
+    }
+
+    #[test]
+    fn removing_rules_keeps_those_opening_elsewhere() {
+        let mut config = config();
+        config.rules[0].group_id = "g1".to_string();
+        remove_browser(&mut config, "ff", OnDeleteRules::Remove).unwrap();
+
+        assert_eq!(targets(&config), [("r1", ""), ("r2", "work")]);
+        assert_eq!(config.rules[0].group_id, "g1");
+    }
+
+    #[test]
+    fn rules_cant_be_reassigned_to_a_missing_browser() {
+        for new_id in ["ff", "gone"] {
+            let mut config = config();
+            let rules = OnDeleteRules::Reassign(new_id.to_string());
+            assert!(remove_browser(&mut config, "ff", rules).is_err());
+            assert_eq!(config.browsers.len(), 2);
+            assert_eq!(targets(&config), targets(&self::config()));
+        }
+    }
+
+    #[test]
+    fn locked_browsers_cant_be_deleted() {
+        let mut config = config();
+        config.browsers[0].locked = true;
+        assert!(remove_browser(&mut config, "ff", OnDeleteRules::Remove).is_err());
+        assert_eq!(config.rules.len(), 3);
+    }
+
+    #[test]
+    fn browsers_used_by_locked_rules_cant_be_deleted() {
+        let mut config = config();
+        config.rules[1].locked = true;
+        assert!(remove_browser(&mut config, "work", OnDeleteRules::Remove).is_err());
+        assert_eq!(config.browsers.len(), 2);
+        assert_eq!(config.rules.len(), 3);
+
+        // Other browsers can still be deleted
+        assert!(remove_browser(&mut config, "ff", OnDeleteRules::Remove).is_ok());
+    }
+
+    #[test]
+    fn browsers_in_a_group_used_by_locked_rules_cant_be_deleted() {
+        let mut config = config();
+        config.rules[0].locked = true;
+        config.rules[0].group_id = "g1".to_string();
+        for id in ["ff", "work"] {
+            assert!(remove_browser(&mut config, id, OnDeleteRules::Remove).is_err());
+        }
+        assert_eq!(config.browsers.len(), 2);
+        assert_eq!(config.groups[0].browser_ids, ["ff", "work"]);
+
+        // Once the group no longer has the browser, it can be deleted
+        config.groups[0].browser_ids.retain(|b| b != "work");
+        assert!(remove_browser(&mut config, "work", OnDeleteRules::Remove).is_ok());
+    }
 }
@@ -0,0 +1,206 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Link files larger than this are not link files, and are opened as-is.
+const MAX_LINK_FILE_SIZE: u64 = 64 * 1024;
+
+/// Resolves a file path or `file://` URL into the URL that should be opened.
+/// Link files (Windows `.url`, macOS `.webloc`, freedesktop `.desktop` of type
+/// Link) resolve to their target, and are an error if they have none; any other
+/// file, such as a local HTML page, resolves to its own `file://` URL. Returns
+/// None if `arg` isn't an existing file.
+pub fn resolve_file(arg: &str) -> Result<Option<String>, String> {
+    let path = match url::Url::parse(arg) {
+        Ok(url) if url.scheme() == "file" => match url.to_file_path() {
+            Ok(path) => path,
+            Err(()) => return Ok(None),
+        },
+        // Windows drive letters parse as one-letter URL schemes
+        Ok(url) if url.scheme().len() > 1 => return Ok(None),
+        _ => PathBuf::from(arg),
+    };
+
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+
+    let parse: fn(&str) -> Option<String> = match ext.as_str() {
+        "url" => parse_internet_shortcut,
+        "webloc" => parse_webloc,
+        "desktop" => parse_desktop_link,
+        _ => return Ok(file_url(&path)),
+    };
+    read_link_file(&path)
+        .and_then(|content| parse(&content))
+        .map(Some)
+        .ok_or_else(|| format!("'{}' isn't a link file with a URL", path.display()))
+}
+
+/// The `file://` URL of a local file.
+fn file_url(path: &Path) -> Option<String> {
+    let path = fs::canonicalize(path).ok()?;
+    // Strip the `\\?\` prefix canonicalize adds on Windows, which file URLs can't represent
+    let path = path
+        .to_str()
+        .and_then(|p| p.strip_prefix(r"\\?\"))
+        .map(PathBuf::from)
+        .unwrap_or(path);
+    Some(url::Url::from_file_path(path).ok()?.to_string())
+}
+
+fn read_link_file(path: &Path) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > MAX_LINK_FILE_SIZE {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    let content = String::from_utf8_lossy(&bytes);
+    Some(content.trim_start_matches('\u{feff}').to_string())
+}
+
+/// Reads `key` from `[section]` of an INI-style file, as used by both Internet
+/// Shortcut and desktop entry files.
+pub fn ini_value<'a>(content: &'a str, section: &str, key: &str) -> Option<&'a str> {
+    let mut in_section = false;
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') && line.ends_with(']') {
+            in_section = line[1..line.len() - 1].eq_ignore_ascii_case(section);
+        } else if in_section {
+            if let Some((k, v)) = line.split_once('=') {
+                if k.trim().eq_ignore_ascii_case(key) {
+                    return Some(v.trim());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Parses a Windows Internet Shortcut (`.url`) file.
+fn parse_internet_shortcut(content: &str) -> Option<String> {
+    let url = ini_value(content, "InternetShortcut", "URL")?;
+    (!url.is_empty()).then(|| url.to_string())
+}
+
+/// Parses a freedesktop desktop entry of type Link.
+fn parse_desktop_link(content: &str) -> Option<String> {
+    if ini_value(content, "Desktop Entry", "Type")? != "Link" {
+        return None;
+    }
+    let url = ini_value(content, "Desktop Entry", "URL")?;
+    (!url.is_empty()).then(|| url.to_string())
+}
+
+/// Parses a macOS `.webloc` file in XML property list format, reading the
+/// string that follows `<key>URL</key>`.
+fn parse_webloc(content: &str) -> Option<String> {
+    let after_key = &content[content.find("<key>URL</key>")? + "<key>URL</key>".len()..];
+    let start = after_key.find("<string>")? + "<string>".len();
+    let end = after_key[start..].find("</string>")? + start;
+
+    let url = after_key[start..end]
+        .trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    (!url.is_empty()).then_some(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_internet_shortcuts() {
+        let shortcut = "[{000214A0-0000-0000-C000-000000000046}]\r\n\
+                        Prop3=19,11\r\n\
+                        [InternetShortcut]\r\n\
+                        IDList=\r\n\
+                        URL = https://example.com/a?b=c\r\n\
+                        IconIndex=0\r\n";
+        assert_eq!(
+            parse_internet_shortcut(shortcut).as_deref(),
+            Some("https://example.com/a?b=c")
+        );
+        assert_eq!(
+            parse_internet_shortcut("[Other]\nURL=https://example.com"),
+            None
+        );
+        assert_eq!(parse_internet_shortcut("[InternetShortcut]\nURL="), None);
+    }
+
+    #[test]
+    fn parses_weblocs() {
+        let webloc = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>URL</key>
+	<string>https://example.com/?a=1&amp;b=2</string>
+</dict>
+</plist>"#;
+        assert_eq!(
+            parse_webloc(webloc).as_deref(),
+            Some("https://example.com/?a=1&b=2")
+        );
+        assert_eq!(
+            parse_webloc("<dict><key>URL</key><string></string></dict>"),
+            None
+        );
+        assert_eq!(parse_webloc("bplist00"), None);
+    }
+
+    #[test]
+    fn parses_desktop_links_only() {
+        let link = "[Desktop Entry]\nName=Docs\nType=Link\nURL=https://example.com/docs\n";
+        assert_eq!(
+            parse_desktop_link(link).as_deref(),
+            Some("https://example.com/docs")
+        );
+        let application = "[Desktop Entry]\nType=Application\nExec=firefox %u\n";
+        assert_eq!(parse_desktop_link(application), None);
+        // Keys of other groups don't count
+        let other = "[Desktop Entry]\nType=Link\n[Desktop Action x]\nURL=https://example.com\n";
+        assert_eq!(parse_desktop_link(other), None);
+    }
+
+    #[test]
+    fn resolves_files() {
+        let dir = std::env::temp_dir().join(format!("pick-browser-links-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.join(name);
+            fs::write(&path, content).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+
+        let link = write(
+            "docs.url",
+            "[InternetShortcut]\nURL=https://example.com/docs\n",
+        );
+        let page = write("page.html", "<html></html>");
+        let application = write("app.desktop", "[Desktop Entry]\nType=Application\n");
+        let results = (
+            resolve_file(&link),
+            resolve_file(&page),
+            resolve_file(&application),
+            resolve_file(&dir.join("missing.url").to_string_lossy()),
+            resolve_file("https://example.com/docs.url"),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(results.0, Ok(Some("https://example.com/docs".to_string())));
+        let page = results.1.unwrap().unwrap();
+        assert!(page.starts_with("file://") && page.ends_with("/page.html"));
+        assert!(results.2.unwrap_err().contains("isn't a link file"));
+        assert_eq!(results.3, Ok(None));
+        assert_eq!(results.4, Ok(None));
+    }
+}
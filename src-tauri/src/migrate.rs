@@ -0,0 +1,663 @@
+use crate::config::{browser_is_installed, known_browser_paths, Browser, Config, Rule};
+use crate::finicky;
+use serde::{Deserialize, Serialize};
+
+/// Other browser pickers whose config can be imported.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Finicky,
+    Browserosaurus,
+    Choosy,
+    Junction,
+}
+
+/// Another tool's config, reduced to what this app can represent.
+#[derive(Debug, Default)]
+pub struct ForeignConfig {
+    pub browsers: Vec<String>, // Browser names or bundle ids, in the tool's order
+    pub rules: Vec<ForeignRule>,
+    pub default_browser: Option<String>,
+    pub unconverted: Vec<String>, // What couldn't be converted, and why
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ForeignRule {
+    pub pattern: String, // Glob pattern, as in `Rule`
+    pub browser: String, // Browser name or bundle id
+}
+
+/// What importing another tool's config changed, or would change.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MigrationReport {
+    pub added: Vec<String>,   // e.g. "Rule '*://github.com/*' opening in Firefox"
+    pub skipped: Vec<String>, // Entries already set up here
+    pub unconverted: Vec<String>, // Constructs that have no equivalent here
+}
+
+/// Browser names and bundle ids other tools use for the browsers in `known_browser_paths`.
+const BROWSER_ALIASES: &[(&str, &str)] = &[
+    ("google chrome", "chrome"),
+    ("com.google.chrome", "chrome"),
+    ("mozilla firefox", "firefox"),
+    ("org.mozilla.firefox", "firefox"),
+    ("microsoft edge", "edge"),
+    ("com.microsoft.edgemac", "edge"),
+    ("com.apple.safari", "safari"),
+];
+
+/// Parses another tool's config file.
+pub fn parse(source: Source, content: &str) -> Result<ForeignConfig, String> {
+    match source {
+        Source::Finicky => finicky::parse(content),
+        Source::Browserosaurus => parse_browserosaurus(content),
+        Source::Choosy => parse_choosy(content),
+        Source::Junction => Err(
+            "Junction has no rules or list of browsers to import: it offers \
+             the applications installed on the system every time"
+                .to_string(),
+        ),
+    }
+}
+
+/// Adds another tool's browsers and rules to the config. Browsers are matched
+/// to the ones here by name; known browsers that aren't set up yet but are
+/// installed are added, and rules for any other browser prompt to choose instead.
+/// Rules go after the ones here, so those still take priority.
+pub fn apply(config: &mut Config, foreign: ForeignConfig) -> MigrationReport {
+    let mut report = MigrationReport {
+        unconverted: foreign.unconverted,
+        ..Default::default()
+    };
+
+    for name in &foreign.browsers {
+        resolve_browser(config, name, &mut report);
+    }
+
+    for rule in foreign.rules {
+        let browser_id = resolve_browser(config, &rule.browser, &mut report).unwrap_or_default();
+        let exists = config
+            .rules
+            .iter()
+            .any(|r| r.pattern.eq_ignore_ascii_case(&rule.pattern) && r.browser_id == browser_id);
+        if exists {
+            report
+                .skipped
+                .push(format!("Rule '{}' already exists", rule.pattern));
+            continue;
+        }
+        report.added.push(format!(
+            "Rule '{}' opening in {}",
+            rule.pattern,
+            if browser_id.is_empty() {
+                "the picker"
+            } else {
+                &rule.browser
+            }
+        ));
+        config.rules.push(Rule {
+            id: cuid2::create_id(),
+            pattern: rule.pattern,
+            browser_id,
+            strip_tracking: true,
+            app_id: String::new(),
+            group_id: String::new(),
+            locked: false,
+        });
+    }
+
+    if let Some(name) = foreign.default_browser {
+        if let Some(id) = resolve_browser(config, &name, &mut report) {
+            if config.fallback.browser_id.is_empty() {
+                config.fallback.browser_id = id;
+                report.added.push(format!("Default browser {}", name));
+            } else {
+                report
+                    .skipped
+                    .push(format!("Default browser {}, as one is already set", name));
+            }
+        }
+    }
+
+    report
+}
+
+/// Finds the browser set up here that another tool's browser name refers to,
+/// adding it if it's a known browser installed on this machine.
+fn resolve_browser(
+    config: &mut Config,
+    name: &str,
+    report: &mut MigrationReport,
+) -> Option<String> {
+    let wanted = normalise_browser_name(name);
+    if let Some(browser) = config
+        .browsers
+        .iter()
+        .find(|b| normalise_browser_name(&b.name) == wanted)
+    {
+        return Some(browser.id.clone());
+    }
+
+    let known = known_browser_paths()
+        .into_iter()
+        .find(|(known, path)| known.eq_ignore_ascii_case(&wanted) && browser_is_installed(path));
+    let Some((known_name, path)) = known else {
+        let note = format!(
+            "Browser {} isn't installed here, so its rules prompt to choose instead",
+            name
+        );
+        if !report.unconverted.contains(&note) {
+            report.unconverted.push(note);
+        }
+        return None;
+    };
+
+    let id = cuid2::create_id();
+    config.browsers.push(Browser {
+        id: id.clone(),
+        name: known_name.to_string(),
+        path: path.to_string(),
+        icon: None,
+        strip_tracking: true,
+        schemes: Vec::new(),
+        hidden: false,
+        locked: false,
+    });
+    report.added.push(format!("Browser '{}'", known_name));
+    Some(id)
+}
+
+/// Lowercases a browser name or bundle id, and maps known aliases to the name
+/// used here, e.g. "Google Chrome" and "com.google.Chrome" to "chrome".
+fn normalise_browser_name(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    let name = name.strip_suffix(".app").unwrap_or(&name);
+    BROWSER_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, known)| known)
+        .to_string()
+}
+
+/// Converts a regex to an approximate glob pattern, if it only uses literal
+/// text, `.*`, `.+` and `https?`. The glob may match more URLs than the regex:
+/// `.+` becomes `*`, which also matches nothing, and `https?` becomes `http*`.
+pub fn regex_to_glob(regex: &str) -> Option<String> {
+    let (anchored_start, regex) = match regex.strip_prefix('^') {
+        Some(rest) => (true, rest),
+        None => (false, regex),
+    };
+    let (anchored_end, regex) = match regex.strip_suffix('$') {
+        Some(rest) if !rest.ends_with('\\') => (true, rest),
+        _ => (false, regex),
+    };
+
+    let mut glob = String::new();
+    if !anchored_start {
+        glob.push('*');
+    }
+    let mut chars = regex.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('*') => return None, // Globs have no literal `*`
+                Some(escaped) if escaped.is_ascii_punctuation() => glob.push(escaped),
+                _ => return None, // Character classes like `\d`
+            },
+            '.' if matches!(chars.peek(), Some('*') | Some('+')) => {
+                chars.next();
+                glob.push('*');
+            }
+            's' if chars.peek() == Some(&'?') && glob.ends_with("http") => {
+                chars.next();
+                glob.push('*');
+            }
+            '.' | '*' | '+' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '|' | '^' | '$' => {
+                return None;
+            }
+            c => glob.push(c),
+        }
+    }
+    if !anchored_end {
+        glob.push('*');
+    }
+
+    while glob.contains("**") {
+        glob = glob.replace("**", "*");
+    }
+    Some(glob)
+}
+
+/// Glob pattern for every URL on a host, e.g. `*://example.com/*`.
+pub fn host_pattern(host: &str) -> String {
+    format!("*://{}/*", host.trim().trim_end_matches('/'))
+}
+
+/// Browserosaurus has no rules, just the browsers it offers, in order. Its
+/// `config.json` lists them as `storage.apps` (or `apps` in older versions).
+fn parse_browserosaurus(content: &str) -> Result<ForeignConfig, String> {
+    let value: serde_json::Value = serde_json::from_str(content)
+        .map_err(|e| format!("Not a valid Browserosaurus config: {}", e))?;
+    let apps = value["storage"]["apps"]
+        .as_array()
+        .or_else(|| value["apps"].as_array())
+        .ok_or_else(|| "Not a Browserosaurus config: it has no list of browsers".to_string())?;
+
+    let mut foreign = ForeignConfig::default();
+    for app in apps {
+        let Some(name) = app["name"].as_str().or_else(|| app["id"].as_str()) else {
+            continue;
+        };
+        if app["isInstalled"].as_bool() == Some(false) {
+            continue;
+        }
+        if app["hotCode"].is_string() || app["hotkey"].is_string() {
+            foreign
+                .unconverted
+                .push(format!("Keyboard shortcut for {}", name));
+        }
+        foreign.browsers.push(name.to_string());
+    }
+    Ok(foreign)
+}
+
+/// Choosy keeps its rules in an XML property list. Each rule has a browser and
+/// a list of conditions on the URL; conditions on anything else, such as the
+/// application the link was clicked in, have no equivalent here.
+fn parse_choosy(content: &str) -> Result<ForeignConfig, String> {
+    if content.starts_with("bplist") {
+        return Err(
+            "This is a binary property list: convert it to XML first, with \
+             `plutil -convert xml1 <file>`"
+                .to_string(),
+        );
+    }
+    let plist = Plist::parse(content)?;
+    let rules = match &plist {
+        Plist::Array(rules) => rules,
+        dict => match dict.get("rules").or_else(|| dict.get("behaviours")) {
+            Some(Plist::Array(rules)) => rules,
+            _ => return Err("Not a Choosy config: it has no list of rules".to_string()),
+        },
+    };
+
+    let mut foreign = ForeignConfig::default();
+    for (index, rule) in rules.iter().enumerate() {
+        let description = rule
+            .get_str("name")
+            .map(|name| format!("Rule '{}'", name))
+            .unwrap_or_else(|| format!("Rule {}", index + 1));
+        let browser = [
+            "browser",
+            "browserName",
+            "browserIdentifier",
+            "bundleIdentifier",
+        ]
+        .iter()
+        .find_map(|key| rule.get_str(key));
+        let Some(browser) = browser else {
+            foreign.unconverted.push(format!(
+                "{}: it doesn't open a browser, e.g. it always prompts",
+                description
+            ));
+            continue;
+        };
+
+        let conditions = match rule.get("conditions") {
+            Some(Plist::Array(conditions)) => conditions.iter().collect(),
+            _ => vec![rule],
+        };
+        let match_all = ["matchAll", "allConditions"]
+            .iter()
+            .any(|key| matches!(rule.get(key), Some(Plist::Bool(true))));
+        if match_all && conditions.len() > 1 {
+            foreign.unconverted.push(format!(
+                "{}: it needs all of several conditions to match",
+                description
+            ));
+            continue;
+        }
+
+        for condition in conditions {
+            match choosy_condition(condition) {
+                Ok(pattern) => foreign.rules.push(ForeignRule {
+                    pattern,
+                    browser: browser.to_string(),
+                }),
+                Err(reason) => foreign
+                    .unconverted
+                    .push(format!("{}: {}", description, reason)),
+            }
+        }
+    }
+    Ok(foreign)
+}
+
+/// Converts a Choosy condition on the URL to a glob pattern.
+fn choosy_condition(condition: &Plist) -> Result<String, String> {
+    let kind = ["type", "kind", "condition"]
+        .iter()
+        .find_map(|key| condition.get_str(key))
+        .unwrap_or_default();
+    let value = ["value", "string", "text"]
+        .iter()
+        .find_map(|key| condition.get_str(key))
+        .ok_or_else(|| "a condition has no value".to_string())?;
+
+    let kind: String = kind
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    match kind.as_str() {
+        "hostis" | "domainis" => Ok(host_pattern(value)),
+        "hostendswith" | "domainendswith" => Ok(host_pattern(&format!("*{}", value))),
+        "hostcontains" | "domaincontains" => Ok(format!("*://*{}*/*", value)),
+        "urlis" => Ok(value.to_string()),
+        "urlcontains" | "contains" => Ok(format!("*{}*", value)),
+        "urlbeginswith" | "urlstartswith" | "beginswith" | "startswith" => {
+            Ok(format!("{}*", value))
+        }
+        "urlendswith" | "endswith" => Ok(format!("*{}", value)),
+        "urlmatches" | "urlmatchesregex" | "regex" => regex_to_glob(value)
+            .ok_or_else(|| format!("the regex '{}' can't be written as a pattern", value)),
+        "" => Err("a condition has no type".to_string()),
+        _ => Err(format!(
+            "conditions of type '{}' have no equivalent here",
+            kind
+        )),
+    }
+}
+
+/// A value in an XML property list.
+#[derive(Debug)]
+enum Plist {
+    Dict(Vec<(String, Plist)>),
+    Array(Vec<Plist>),
+    String(String),
+    Bool(bool),
+    Other, // Numbers, dates and data, which no importer needs
+}
+
+impl Plist {
+    fn parse(content: &str) -> Result<Self, String> {
+        let start = content
+            .find("<plist")
+            .and_then(|i| content[i..].find('>').map(|j| i + j + 1))
+            .ok_or_else(|| "Not a property list".to_string())?;
+        let mut tags = PlistTags {
+            content,
+            position: start,
+        };
+        tags.value()
+    }
+
+    fn get(&self, key: &str) -> Option<&Plist> {
+        match self {
+            Plist::Dict(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn get_str(&self, key: &str) -> Option<&str> {
+        match self.get(key) {
+            Some(Plist::String(value)) if !value.is_empty() => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the elements of an XML property list in order.
+struct PlistTags<'a> {
+    content: &'a str,
+    position: usize,
+}
+
+impl PlistTags<'_> {
+    /// The next tag's name, skipping comments, with a leading `/` for closing
+    /// tags and a trailing `/` for empty ones.
+    fn tag(&mut self) -> Result<&str, String> {
+        loop {
+            let rest = &self.content[self.position..];
+            let start = rest
+                .find('<')
+                .ok_or_else(|| "Unexpected end of property list".to_string())?;
+            if rest[start..].starts_with("<!--") {
+                let end = rest[start..]
+                    .find("-->")
+                    .ok_or_else(|| "Unclosed comment in property list".to_string())?;
+                self.position += start + end + 3;
+                continue;
+            }
+            let end = rest[start..]
+                .find('>')
+                .ok_or_else(|| "Unclosed tag in property list".to_string())?;
+            self.position += start + end + 1;
+            return Ok(rest[start + 1..start + end].trim());
+        }
+    }
+
+    /// The text up to the closing tag of `name`, with entities decoded.
+    fn text(&mut self, name: &str) -> Result<String, String> {
+        let closing = format!("</{}>", name);
+        let rest = &self.content[self.position..];
+        let end = rest
+            .find(&closing)
+            .ok_or_else(|| format!("Unclosed <{}> in property list", name))?;
+        self.position += end + closing.len();
+        Ok(rest[..end]
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"))
+    }
+
+    fn value(&mut self) -> Result<Plist, String> {
+        let tag = self.tag()?.to_string();
+        match tag.as_str() {
+            "dict" => {
+                let mut entries = Vec::new();
+                loop {
+                    match self.tag()? {
+                        "/dict" => return Ok(Plist::Dict(entries)),
+                        "key" => {
+                            let key = self.text("key")?;
+                            entries.push((key, self.value()?));
+                        }
+                        other => return Err(format!("Unexpected <{}> in property list", other)),
+                    }
+                }
+            }
+            "array" => {
+                let mut items = Vec::new();
+                loop {
+                    let position = self.position;
+                    if self.tag()? == "/array" {
+                        return Ok(Plist::Array(items));
+                    }
+                    self.position = position;
+                    items.push(self.value()?);
+                }
+            }
+            "dict/" => Ok(Plist::Dict(Vec::new())),
+            "array/" => Ok(Plist::Array(Vec::new())),
+            "string/" => Ok(Plist::String(String::new())),
+            "true/" => Ok(Plist::Bool(true)),
+            "false/" => Ok(Plist::Bool(false)),
+            "string" => Ok(Plist::String(self.text("string")?)),
+            "integer" | "real" | "date" | "data" => {
+                self.text(&tag)?;
+                Ok(Plist::Other)
+            }
+            other => Err(format!("Unexpected <{}> in property list", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_support::{browser, rule};
+
+    #[test]
+    fn regex_to_glob_converts_simple_regexes() {
+        assert_eq!(
+            regex_to_glob(r"^https?://(www\.)?example\.com/.*$"),
+            None // Optional groups can't be written as a glob
+        );
+        assert_eq!(
+            regex_to_glob(r"^https?://example\.com/.*$").as_deref(),
+            Some("http*://example.com/*")
+        );
+        assert_eq!(
+            regex_to_glob(r"zoom\.us/j/").as_deref(),
+            Some("*zoom.us/j/*")
+        );
+        assert_eq!(
+            regex_to_glob(r"^https://.+\.slack\.com$").as_deref(),
+            Some("https://*.slack.com")
+        );
+        assert_eq!(regex_to_glob(r"/item/\d+"), None);
+        assert_eq!(regex_to_glob(r"a|b"), None);
+        assert_eq!(regex_to_glob(r"^https://example\.com/\*$"), None);
+    }
+
+    #[test]
+    fn browser_names_are_matched_through_aliases() {
+        assert_eq!(normalise_browser_name("Google Chrome"), "chrome");
+        assert_eq!(normalise_browser_name("com.google.Chrome"), "chrome");
+        assert_eq!(normalise_browser_name("Firefox.app"), "firefox");
+        assert_eq!(normalise_browser_name("Arc"), "arc");
+    }
+
+    #[test]
+    fn apply_maps_browsers_and_reports_unknown_ones() {
+        let mut config = Config {
+            browsers: vec![browser("ff", "Firefox")],
+            rules: vec![rule("r", "*://github.com/*", "ff")],
+            ..Default::default()
+        };
+        let foreign = ForeignConfig {
+            rules: vec![
+                ForeignRule {
+                    pattern: "*://github.com/*".to_string(),
+                    browser: "org.mozilla.firefox".to_string(),
+                },
+                ForeignRule {
+                    pattern: "*://gitlab.com/*".to_string(),
+                    browser: "Mozilla Firefox".to_string(),
+                },
+                ForeignRule {
+                    pattern: "*://figma.com/*".to_string(),
+                    browser: "Not A Browser".to_string(),
+                },
+            ],
+            default_browser: Some("Firefox".to_string()),
+            ..Default::default()
+        };
+
+        let report = apply(&mut config, foreign);
+
+        let rules: Vec<_> = config
+            .rules
+            .iter()
+            .map(|r| (r.pattern.as_str(), r.browser_id.as_str()))
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                ("*://github.com/*", "ff"),
+                ("*://gitlab.com/*", "ff"),
+                ("*://figma.com/*", "")
+            ]
+        );
+        assert_eq!(config.fallback.browser_id, "ff");
+        assert_eq!(
+            report.skipped,
+            vec!["Rule '*://github.com/*' already exists"]
+        );
+        assert_eq!(
+            report.unconverted,
+            vec![
+                "Browser Not A Browser isn't installed here, so its rules prompt to choose instead"
+            ]
+        );
+    }
+
+    #[test]
+    fn browserosaurus_lists_installed_browsers() {
+        let foreign = parse_browserosaurus(
+            r#"{"storage": {"apps": [
+                {"name": "Firefox", "hotCode": "KeyF", "isInstalled": true},
+                {"name": "Safari", "hotCode": null, "isInstalled": false},
+                {"name": "Google Chrome", "hotCode": null}
+            ]}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(foreign.browsers, ["Firefox", "Google Chrome"]);
+        assert_eq!(foreign.unconverted, ["Keyboard shortcut for Firefox"]);
+        assert!(parse_browserosaurus("{}").is_err());
+    }
+
+    #[test]
+    fn choosy_rules_are_converted_by_condition() {
+        let foreign = parse_choosy(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "PropertyList-1.0.dtd">
+            <plist version="1.0">
+            <dict>
+                <key>rules</key>
+                <array>
+                    <dict>
+                        <key>name</key><string>Work</string>
+                        <key>browser</key><string>com.google.Chrome</string>
+                        <key>conditions</key>
+                        <array>
+                            <dict>
+                                <key>type</key><string>Host Is</string>
+                                <key>value</key><string>jira.example.com</string>
+                            </dict>
+                            <dict>
+                                <key>type</key><string>URL Contains</string>
+                                <key>value</key><string>q=a&amp;b</string>
+                            </dict>
+                            <dict>
+                                <key>type</key><string>Source Application</string>
+                                <key>value</key><string>Slack</string>
+                            </dict>
+                        </array>
+                    </dict>
+                    <dict>
+                        <key>name</key><string>Ask</string>
+                        <key>enabled</key><true/>
+                    </dict>
+                </array>
+            </dict>
+            </plist>"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            foreign.rules,
+            [
+                ForeignRule {
+                    pattern: "*://jira.example.com/*".to_string(),
+                    browser: "com.google.Chrome".to_string(),
+                },
+                ForeignRule {
+                    pattern: "*q=a&b*".to_string(),
+                    browser: "com.google.Chrome".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            foreign.unconverted,
+            [
+                "Rule 'Work': conditions of type 'sourceapplication' have no equivalent here",
+                "Rule 'Ask': it doesn't open a browser, e.g. it always prompts",
+            ]
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use crate::config::{Config, RewriteRule};
+use crate::{expand, unwrap};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+/// One change made to a URL on its way to the routing rules.
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineStep {
+    pub stage: String,       // "unwrap", "expand", "rewrite" or "rewrite_skipped"
+    pub description: String, // What was applied, e.g. the rewrite pattern
+    pub url: String,         // The URL after this step
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineResult {
+    pub original: String,
+    pub url: String, // The URL after all steps, as seen by the routing rules
+    pub steps: Vec<PipelineStep>,
+}
+
+/// Runs a URL through every transformation stage, in a fixed order: redirect
+/// wrappers are unwrapped, short links are expanded (and anything they expand
+/// to is unwrapped again), then rewrite rules are applied in config order.
+/// Each rewrite sees the output of the previous one, and is applied at most once.
+/// Rewrites that can't be applied are recorded as skipped, leaving the URL as it was.
+pub fn run(url: &str, config: &Config) -> PipelineResult {
+    let mut steps: Vec<PipelineStep> = Vec::new();
+    push_unwrap_steps(&mut steps, url, config);
+
+    let current = steps.last().map(|s| s.url.as_str()).unwrap_or(url);
+    let expanded = expand::expand_hops(current, &config.short_links);
+    if !expanded.is_empty() {
+        steps.extend(expanded.into_iter().map(|hop| PipelineStep {
+            stage: "expand".to_string(),
+            description: "Followed short link redirect".to_string(),
+            url: hop,
+        }));
+        push_unwrap_steps(&mut steps, url, config);
+    }
+
+    for rewrite in &config.rewrites {
+        let current = steps.last().map(|s| s.url.as_str()).unwrap_or(url);
+        let (stage, description, url) = match apply_rewrite(current, rewrite) {
+            Ok(None) => continue,
+            Ok(Some(rewritten)) => (
+                "rewrite",
+                format!("{} → {}", rewrite.pattern, rewrite.replacement),
+                rewritten,
+            ),
+            Err(reason) => (
+                "rewrite_skipped",
+                format!(
+                    "Skipped {} → {}: {}",
+                    rewrite.pattern, rewrite.replacement, reason
+                ),
+                current.to_string(),
+            ),
+        };
+        steps.push(PipelineStep {
+            stage: stage.to_string(),
+            description,
+            url,
+        });
+    }
+
+    PipelineResult {
+        original: url.to_string(),
+        url: steps
+            .last()
+            .map(|s| s.url.clone())
+            .unwrap_or_else(|| url.to_string()),
+        steps,
+    }
+}
+
+/// Unwraps redirect wrappers from the latest URL, recording a step for each one removed.
+fn push_unwrap_steps(steps: &mut Vec<PipelineStep>, url: &str, config: &Config) {
+    let current = steps.last().map(|s| s.url.as_str()).unwrap_or(url);
+    let hops = unwrap::unwrap_hops(current, &config.unwrappers);
+    steps.extend(hops.into_iter().map(|hop| PipelineStep {
+        stage: "unwrap".to_string(),
+        description: "Removed redirect wrapper".to_string(),
+        url: hop,
+    }));
+}
+
+/// Applies a single rewrite rule, replacing the first match of its pattern.
+/// Returns None if the pattern doesn't match or leaves the URL unchanged, and
+/// an error if the pattern is invalid or the result is no longer an absolute URL.
+fn apply_rewrite(url: &str, rewrite: &RewriteRule) -> Result<Option<String>, String> {
+    let re = compiled_pattern(&rewrite.pattern)?;
+    if !re.is_match(url) {
+        return Ok(None);
+    }
+
+    let rewritten = re.replace(url, rewrite.replacement.as_str()).into_owned();
+    if rewritten == url {
+        return Ok(None);
+    }
+    if url::Url::parse(&rewritten).is_err() {
+        return Err(format!("'{}' isn't an absolute URL", rewritten));
+    }
+
+    Ok(Some(rewritten))
+}
+
+/// Compiles a rewrite pattern, reusing the regex from earlier calls, as every
+/// URL opened goes through every rewrite.
+fn compiled_pattern(pattern: &str) -> Result<regex::Regex, String> {
+    static CACHE: LazyLock<Mutex<HashMap<String, regex::Regex>>> = LazyLock::new(Default::default);
+
+    let mut cache = CACHE.lock().unwrap();
+    if let Some(re) = cache.get(pattern) {
+        return Ok(re.clone());
+    }
+    let re = regex::Regex::new(pattern).map_err(|e| format!("invalid pattern: {}", e))?;
+    cache.insert(pattern.to_string(), re.clone());
+    Ok(re)
+}
+
+/// Checks a rewrite before it's saved: its pattern must be a valid regex, and
+/// its replacement may only refer to capture groups the pattern has.
+pub fn validate_rewrite(pattern: &str, replacement: &str) -> Result<(), String> {
+    let re = regex::Regex::new(pattern)
+        .map_err(|e| format!("Invalid rewrite pattern '{}': {}", pattern, e))?;
+
+    let mut rest = replacement;
+    while let Some(index) = rest.find('$') {
+        rest = &rest[index + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            rest = after; // `$$` is a literal `$`
+            continue;
+        }
+        let (group, after) = match rest.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", rest),
+            },
+            None => {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+        rest = after;
+        if group.is_empty() {
+            continue; // A lone `$` is kept as it is
+        }
+        let exists = match group.parse::<usize>() {
+            Ok(number) => number < re.captures_len(),
+            Err(_) => re.capture_names().flatten().any(|name| name == group),
+        };
+        if !exists {
+            return Err(format!(
+                "Rewrite replacement '{}' refers to group '{}', which pattern '{}' doesn't have",
+                replacement, group, pattern
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_rewrites(rewrites: &[(&str, &str)]) -> Config {
+        Config {
+            rewrites: rewrites
+                .iter()
+                .enumerate()
+                .map(|(index, (pattern, replacement))| RewriteRule {
+                    id: index.to_string(),
+                    pattern: pattern.to_string(),
+                    replacement: replacement.to_string(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn stages(result: &PipelineResult) -> Vec<&str> {
+        result.steps.iter().map(|s| s.stage.as_str()).collect()
+    }
+
+    #[test]
+    fn rewrites_substitute_captures() {
+        let config = with_rewrites(&[
+            (
+                r"^https://www\.reddit\.com/(.*)$",
+                "https://old.reddit.com/$1",
+            ),
+            (
+                r"^https://youtu\.be/(?<video>\w+)",
+                "https://www.youtube.com/watch?v=${video}",
+            ),
+        ]);
+        let result = run("https://www.reddit.com/r/rust/", &config);
+        assert_eq!(result.url, "https://old.reddit.com/r/rust/");
+        let result = run("https://youtu.be/abc123", &config);
+        assert_eq!(result.url, "https://www.youtube.com/watch?v=abc123");
+        assert_eq!(stages(&result), ["rewrite"]);
+    }
+
+    #[test]
+    fn rewrites_apply_in_order_each_at_most_once() {
+        let config = with_rewrites(&[
+            ("^http://", "https://"),
+            (r"^https://example\.com", "https://www.example.com"),
+            (r"www\.", "www.www."),
+        ]);
+        let result = run("http://example.com/a", &config);
+        assert_eq!(result.url, "https://www.www.example.com/a");
+        assert_eq!(stages(&result), ["rewrite", "rewrite", "rewrite"]);
+
+        // A later rewrite doesn't feed an earlier one
+        let reversed = with_rewrites(&[
+            (r"^https://example\.com", "https://www.example.com"),
+            ("^http://", "https://"),
+        ]);
+        let result = run("http://example.com/a", &reversed);
+        assert_eq!(result.url, "https://example.com/a");
+    }
+
+    #[test]
+    fn skipped_rewrites_are_in_the_trace() {
+        let config = with_rewrites(&[
+            ("(", "x"),
+            ("^https://", "not a url "),
+            ("nowhere", "x"),
+            ("/a$", "/b"),
+        ]);
+        let result = run("https://example.com/a", &config);
+        assert_eq!(result.url, "https://example.com/b");
+        assert_eq!(
+            stages(&result),
+            ["rewrite_skipped", "rewrite_skipped", "rewrite"]
+        );
+        assert!(result.steps[0].description.contains("invalid pattern"));
+        assert_eq!(result.steps[1].url, "https://example.com/a");
+    }
+
+    #[test]
+    fn validates_rewrites_before_saving() {
+        assert!(
+            validate_rewrite("^https://(www\\.)?(?<host>[^/]+)", "https://$2/${host}$$1").is_ok()
+        );
+        assert!(validate_rewrite("(", "").is_err());
+        assert!(validate_rewrite("^https://(.*)", "https://$2").is_err());
+        assert!(validate_rewrite("^https://(.*)", "https://${name}").is_err());
+        assert!(validate_rewrite("^https://(.*)", "https://$1_x").is_err());
+    }
+}
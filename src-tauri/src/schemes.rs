@@ -0,0 +1,107 @@
+/// Schemes Pick Browser always handles, and which browsers handle by default.
+pub const WEB_SCHEMES: &[&str] = &["http", "https"];
+
+/// Schemes the macOS app bundle declares (`CFBundleURLTypes` in Info.plist). Launch Services
+/// only hands an app the schemes its bundle declares, so on macOS these are the only ones
+/// Pick Browser can be registered for. Keep in sync with Info.plist.
+pub const MACOS_BUNDLE_SCHEMES: &[&str] = &[
+    "http", "https", "mailto", "tel", "sms", "ftp", "webcal", "zoommtg", "msteams",
+];
+
+/// File name of the desktop entry that registers Pick Browser as a URL handler on Linux.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub const DESKTOP_ENTRY_NAME: &str = "pick-browser-url-handler.desktop";
+
+/// Validates and lowercases a URL scheme name (e.g. "mailto", "zoommtg"),
+/// accepting an optional trailing ':'.
+pub fn normalise_scheme(scheme: &str) -> Result<String, String> {
+    let scheme = scheme.trim().trim_end_matches(':').to_lowercase();
+
+    // RFC 3986: ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )
+    let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    if !valid {
+        return Err(format!("Invalid URL scheme '{}'", scheme));
+    }
+
+    Ok(scheme)
+}
+
+/// All schemes Pick Browser should be registered for: the web schemes plus
+/// any extra ones from the config.
+pub fn registered_schemes(extra: &[String]) -> Vec<String> {
+    let mut schemes: Vec<String> = WEB_SCHEMES.iter().map(|s| s.to_string()).collect();
+    for scheme in extra {
+        if !schemes.contains(scheme) {
+            schemes.push(scheme.clone());
+        }
+    }
+    schemes
+}
+
+/// Whether a command-line argument is a URL with one of the registered schemes.
+pub fn is_openable_url(arg: &str, extra: &[String]) -> bool {
+    let Ok(url) = url::Url::parse(arg) else {
+        return false;
+    };
+    registered_schemes(extra).iter().any(|s| s == url.scheme())
+}
+
+/// Checks Pick Browser can be registered for a scheme on this platform.
+pub fn check_registrable(scheme: &str) -> Result<(), String> {
+    if cfg!(target_os = "macos") && !MACOS_BUNDLE_SCHEMES.contains(&scheme) {
+        return Err(format!(
+            "On macOS, Pick Browser can only be registered for {}",
+            MACOS_BUNDLE_SCHEMES.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// The desktop entry that registers Pick Browser for URL schemes on Linux. It's
+/// hidden from menus, as it only exists to be picked as the schemes' handler.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub fn desktop_entry(exe_path: &str, schemes: &[String]) -> String {
+    // Desktop entry quoting: the path goes in double quotes with `"`, `` ` ``, `$` and `\`
+    // escaped, then backslashes are escaped again, as in any desktop entry value
+    let mut exe = String::new();
+    for c in exe_path.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            exe.push('\\');
+        }
+        exe.push(c);
+    }
+    let mime_types: String = schemes
+        .iter()
+        .map(|scheme| format!("x-scheme-handler/{};", scheme))
+        .collect();
+
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=Pick Browser\n\
+         Exec=\"{}\" %u\n\
+         NoDisplay=true\n\
+         MimeType={}\n",
+        exe.replace('\\', "\\\\"),
+        mime_types
+    )
+}
+
+#[cfg(all(test, not(any(target_os = "windows", target_os = "macos"))))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn desktop_entry_quotes_the_path_and_lists_the_schemes() {
+        let schemes = registered_schemes(&["mailto".to_string()]);
+        let entry = desktop_entry(r#"/opt/my "apps"/$pick\browser"#, &schemes);
+
+        assert!(entry.contains(r#"Exec="/opt/my \\"apps\\"/\\$pick\\\\browser" %u"#));
+        assert!(entry.contains(
+            "MimeType=x-scheme-handler/http;x-scheme-handler/https;x-scheme-handler/mailto;\n"
+        ));
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+
+/// A temporary override of the rules, e.g. "open everything in Firefox for the
+/// next hour" or "always prompt for 30 minutes".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemporaryOverride {
+    pub browser_id: String, // Browser to open everything in (empty = always prompt)
+    pub expires_at: u64,    // Unix timestamp, in seconds
+}
+
+impl TemporaryOverride {
+    fn new(browser_id: String, minutes: u32, now: u64) -> Self {
+        Self {
+            browser_id,
+            expires_at: now + u64::from(minutes) * 60,
+        }
+    }
+
+    fn has_expired(&self, now: u64) -> bool {
+        self.expires_at <= now
+    }
+}
+
+/// Holds the active override. It is also written to disk, so it survives the
+/// app being closed and reopened, as it is for every link.
+pub struct OverrideState(Mutex<Option<TemporaryOverride>>);
+
+impl OverrideState {
+    /// Load the saved override, if there is one.
+    pub fn load(app_handle: &tauri::AppHandle) -> Self {
+        let saved = get_override_path(app_handle)
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok());
+        Self(Mutex::new(saved))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn get_override_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
+
+    Ok(app_data_dir.join("override.json"))
+}
+
+fn save(app_handle: &tauri::AppHandle, value: Option<&TemporaryOverride>) -> Result<(), String> {
+    let path = get_override_path(app_handle)?;
+
+    let Some(value) = value else {
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| format!("Failed to remove override: {}", e))?;
+        }
+        return Ok(());
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create config directory: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize override: {}", e))?;
+    fs::write(&path, content).map_err(|e| format!("Failed to write override: {}", e))
+}
+
+/// Get the active override. An expired override is cleared and not returned.
+pub fn active(app_handle: &tauri::AppHandle) -> Option<TemporaryOverride> {
+    let state = app_handle.state::<OverrideState>();
+    let mut current = state.0.lock().unwrap();
+
+    if current.as_ref().is_some_and(|o| o.has_expired(now())) {
+        *current = None;
+        let _ = save(app_handle, None);
+    }
+
+    current.clone()
+}
+
+/// Override the rules for the next `minutes`, replacing any existing override.
+pub fn set(
+    app_handle: &tauri::AppHandle,
+    browser_id: String,
+    minutes: u32,
+) -> Result<TemporaryOverride, String> {
+    let value = TemporaryOverride::new(browser_id, minutes, now());
+    save(app_handle, Some(&value))?;
+
+    let state = app_handle.state::<OverrideState>();
+    *state.0.lock().unwrap() = Some(value.clone());
+
+    Ok(value)
+}
+
+/// Cancel the active override, going back to the rules.
+pub fn clear(app_handle: &tauri::AppHandle) -> Result<(), String> {
+    save(app_handle, None)?;
+
+    let state = app_handle.state::<OverrideState>();
+    *state.0.lock().unwrap() = None;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_last_for_their_minutes() {
+        let value = TemporaryOverride::new("ff".to_string(), 30, 1_000);
+        assert_eq!(value.expires_at, 1_000 + 30 * 60);
+        assert!(!value.has_expired(1_000));
+        assert!(!value.has_expired(1_000 + 30 * 60 - 1));
+        assert!(value.has_expired(1_000 + 30 * 60));
+        assert!(value.has_expired(u64::MAX));
+    }
+
+    #[test]
+    fn zero_minutes_expires_at_once() {
+        let value = TemporaryOverride::new(String::new(), 0, 1_000);
+        assert!(value.has_expired(1_000));
+    }
+
+    #[test]
+    fn saved_overrides_read_back() {
+        let value = TemporaryOverride::new("ff".to_string(), 60, now());
+        let saved = serde_json::to_string_pretty(&value).unwrap();
+        let read: TemporaryOverride = serde_json::from_str(&saved).unwrap();
+        assert_eq!(read.browser_id, "ff");
+        assert_eq!(read.expires_at, value.expires_at);
+        assert!(!read.has_expired(now()));
+    }
+}
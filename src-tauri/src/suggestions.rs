@@ -0,0 +1,209 @@
+use crate::config::{Config, PickStat, Rule};
+use crate::glob_to_regex;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleSuggestion {
+    pub origin: String,
+    pub pattern: String, // Glob pattern the accepted rule would use
+    pub browser_id: String,
+    pub count: u32,
+}
+
+/// Returns the origin (scheme, host and non-default port) of a URL, matching
+/// what the browser's `URL.origin` gives the frontend.
+pub fn url_origin(url: &str) -> Option<String> {
+    let origin = url::Url::parse(url).ok()?.origin();
+    origin.is_tuple().then(|| origin.ascii_serialization())
+}
+
+/// Builds the rule pattern for an origin, the same way the "Create rule"
+/// section does when neither the path nor the query is included.
+pub fn pattern_for_origin(origin: &str) -> String {
+    format!("{}*", origin)
+}
+
+/// Records that the user manually opened `url` in `browser_id`.
+/// Picking a different browser for an origin restarts its count.
+pub fn record_pick(config: &mut Config, url: &str, browser_id: &str) {
+    let Some(origin) = url_origin(url) else {
+        return;
+    };
+
+    let picks = &mut config.suggestions.picks;
+    match picks.iter_mut().find(|p| p.origin == origin) {
+        Some(stat) if stat.browser_id == browser_id => stat.count += 1,
+        Some(stat) => {
+            stat.browser_id = browser_id.to_string();
+            stat.count = 1;
+        }
+        None => picks.push(PickStat {
+            origin,
+            browser_id: browser_id.to_string(),
+            count: 1,
+        }),
+    }
+}
+
+/// Returns the rules worth suggesting: origins picked often enough (the configured
+/// threshold, 3 by default), not ignored, whose browser still exists, and which
+/// no existing rule already covers.
+pub fn get_suggestions(config: &Config) -> Vec<RuleSuggestion> {
+    let rule_regexes: Vec<regex::Regex> = config
+        .rules
+        .iter()
+        .filter_map(|r| glob_to_regex(&r.pattern).ok())
+        .collect();
+
+    config
+        .suggestions
+        .picks
+        .iter()
+        .filter(|p| p.count >= config.suggestions.threshold.max(1))
+        .filter(|p| !config.suggestions.ignored_origins.contains(&p.origin))
+        .filter(|p| config.browsers.iter().any(|b| b.id == p.browser_id))
+        .filter(|p| {
+            let root = format!("{}/", p.origin);
+            !rule_regexes.iter().any(|re| re.is_match(&root))
+        })
+        .map(|p| RuleSuggestion {
+            origin: p.origin.clone(),
+            pattern: pattern_for_origin(&p.origin),
+            browser_id: p.browser_id.clone(),
+            count: p.count,
+        })
+        .collect()
+}
+
+/// Turns the suggestion for `origin` into a rule and forgets its pick count.
+pub fn accept(config: &mut Config, origin: &str) -> Result<(), String> {
+    let suggestion = get_suggestions(config)
+        .into_iter()
+        .find(|s| s.origin == origin)
+        .ok_or_else(|| format!("No rule suggestion for '{}'", origin))?;
+
+    config.rules.push(Rule {
+        id: cuid2::create_id(),
+        pattern: suggestion.pattern,
+        browser_id: suggestion.browser_id,
+        strip_tracking: true,
+        app_id: String::new(),
+        group_id: String::new(),
+        locked: false,
+    });
+    config.suggestions.picks.retain(|p| p.origin != origin);
+
+    Ok(())
+}
+
+/// Dismisses the suggestion for `origin`. A plain dismissal only resets the
+/// count, so it can be suggested again later; `never` ignores it for good.
+pub fn dismiss(config: &mut Config, origin: &str, never: bool) {
+    config.suggestions.picks.retain(|p| p.origin != origin);
+
+    if never
+        && !config
+            .suggestions
+            .ignored_origins
+            .iter()
+            .any(|o| o == origin)
+    {
+        config.suggestions.ignored_origins.push(origin.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_support::{self, rule};
+
+    fn config() -> Config {
+        let mut config = test_support::config();
+        config.rules = vec![rule("r1", "*://docs.example.com/*", "ff")];
+        config
+    }
+
+    fn pick(config: &mut Config, url: &str, browser_id: &str, times: u32) {
+        for _ in 0..times {
+            record_pick(config, url, browser_id);
+        }
+    }
+
+    fn suggested(config: &Config) -> Vec<(String, String, u32)> {
+        get_suggestions(config)
+            .into_iter()
+            .map(|s| (s.origin, s.browser_id, s.count))
+            .collect()
+    }
+
+    #[test]
+    fn suggests_once_the_threshold_is_crossed() {
+        let mut config = config();
+        pick(&mut config, "https://github.com/a", "work", 2);
+        assert!(suggested(&config).is_empty());
+
+        record_pick(&mut config, "https://github.com/b?c=d", "work");
+        assert_eq!(
+            suggested(&config),
+            [("https://github.com".to_string(), "work".to_string(), 3)]
+        );
+
+        // Picking another browser restarts the count
+        record_pick(&mut config, "https://github.com/", "ff");
+        assert!(suggested(&config).is_empty());
+    }
+
+    #[test]
+    fn threshold_comes_from_the_config() {
+        let mut config = config();
+        config.suggestions.threshold = 5;
+        pick(&mut config, "https://github.com/", "work", 4);
+        assert!(suggested(&config).is_empty());
+        record_pick(&mut config, "https://github.com/", "work");
+        assert_eq!(suggested(&config).len(), 1);
+
+        let default: Config = serde_json::from_str(r#"{"browsers": []}"#).unwrap();
+        assert_eq!(default.suggestions.threshold, 3);
+    }
+
+    #[test]
+    fn skips_origins_already_covered_or_whose_browser_is_gone() {
+        let mut config = config();
+        pick(&mut config, "https://docs.example.com/guide", "work", 3);
+        pick(&mut config, "https://gitlab.com/", "deleted", 3);
+        assert!(suggested(&config).is_empty());
+    }
+
+    #[test]
+    fn dismissing_suppresses_the_suggestion() {
+        let mut config = config();
+        pick(&mut config, "https://github.com/", "work", 3);
+        pick(&mut config, "https://gitlab.com/", "work", 3);
+
+        // A plain dismissal starts counting again
+        dismiss(&mut config, "https://github.com", false);
+        assert_eq!(suggested(&config).len(), 1);
+        pick(&mut config, "https://github.com/", "work", 3);
+        assert_eq!(suggested(&config).len(), 2);
+
+        dismiss(&mut config, "https://github.com", true);
+        pick(&mut config, "https://github.com/", "work", 3);
+        let origins: Vec<_> = suggested(&config).into_iter().map(|s| s.0).collect();
+        assert_eq!(origins, ["https://gitlab.com"]);
+    }
+
+    #[test]
+    fn accepting_creates_a_rule() {
+        let mut config = config();
+        pick(&mut config, "https://github.com:8443/x", "work", 3);
+
+        accept(&mut config, "https://github.com:8443").unwrap();
+
+        let rule = config.rules.last().unwrap();
+        assert_eq!(rule.pattern, "https://github.com:8443*");
+        assert_eq!(rule.browser_id, "work");
+        assert!(config.suggestions.picks.is_empty());
+        assert!(suggested(&config).is_empty());
+        assert!(accept(&mut config, "https://github.com:8443").is_err());
+    }
+}
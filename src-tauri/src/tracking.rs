@@ -0,0 +1,188 @@
+use crate::config::{Browser, Config, TrackingSettings};
+use crate::{check_rule, glob_to_regex, RuleCheck};
+
+/// Built-in tracking parameters, as glob patterns matched against parameter names.
+const BUILTIN_TRACKING_PARAMS: &[&str] = &[
+    "utm_*",
+    "fbclid",
+    "gclid",
+    "gclsrc",
+    "dclid",
+    "gbraid",
+    "wbraid",
+    "msclkid",
+    "yclid",
+    "twclid",
+    "ttclid",
+    "igshid",
+    "li_fat_id",
+    "mc_eid",
+    "mc_cid",
+    "_hsenc",
+    "_hsmi",
+    "mkt_tok",
+    "oly_anon_id",
+    "oly_enc_id",
+    "vero_id",
+    "__s",
+];
+
+/// Strips tracking parameters from a URL about to open in `browser`, unless
+/// stripping is disabled globally, for the browser, or by the rule the URL
+/// matches. A rule with an invalid pattern counts as no match, rather than
+/// stopping the browser opening.
+pub fn strip_for_browser(config: &Config, browser: &Browser, url: &str) -> String {
+    let rule = config
+        .rules
+        .iter()
+        .find(|r| matches!(check_rule(config, r, url), Ok(RuleCheck::Applies)));
+    let strip =
+        config.tracking.enabled && browser.strip_tracking && rule.is_none_or(|r| r.strip_tracking);
+    if strip {
+        strip_tracking_params(url, &config.tracking)
+    } else {
+        url.to_string()
+    }
+}
+
+/// Removes tracking parameters (built-in and user-defined) from a URL's query
+/// string. Other parameters keep their original order and encoding. URLs whose
+/// host matches one of the configured exceptions are returned unchanged.
+pub fn strip_tracking_params(url: &str, settings: &TrackingSettings) -> String {
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return url.to_string();
+    };
+    let Some(query) = parsed.query() else {
+        return url.to_string();
+    };
+
+    let host = parsed.host_str().unwrap_or_default();
+    let is_exception = settings
+        .exceptions
+        .iter()
+        .filter_map(|pattern| glob_to_regex(pattern).ok())
+        .any(|re| re.is_match(host));
+    if is_exception {
+        return url.to_string();
+    }
+
+    // Invalid user patterns are skipped rather than failing the whole open
+    let params: Vec<regex::Regex> = BUILTIN_TRACKING_PARAMS
+        .iter()
+        .copied()
+        .chain(settings.params.iter().map(String::as_str))
+        .filter_map(|pattern| glob_to_regex(pattern).ok())
+        .collect();
+
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or_default();
+            let key = url::form_urlencoded::parse(key.as_bytes())
+                .next()
+                .map(|(k, _)| k.into_owned())
+                .unwrap_or_default();
+            !params.iter().any(|re| re.is_match(&key))
+        })
+        .collect();
+
+    if kept.len() == query.split('&').count() {
+        return url.to_string();
+    }
+
+    let kept = kept.join("&");
+    parsed.set_query((!kept.is_empty()).then_some(kept.as_str()));
+    parsed.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_support::{browser, invalid_pattern, rule};
+    use crate::config::Rule;
+
+    fn strip(url: &str) -> String {
+        strip_tracking_params(url, &TrackingSettings::default())
+    }
+
+    #[test]
+    fn strips_builtin_params_and_keeps_the_rest() {
+        assert_eq!(
+            strip("https://example.com/a?utm_source=x&id=1&fbclid=y&UTM_Medium=z&b=%20c#top"),
+            "https://example.com/a?id=1&b=%20c#top"
+        );
+        assert_eq!(
+            strip("https://example.com/?gclid=1&msclkid=2"),
+            "https://example.com/"
+        );
+    }
+
+    #[test]
+    fn drops_the_query_when_nothing_is_left() {
+        assert_eq!(
+            strip("https://example.com/page?utm_campaign=spring#section"),
+            "https://example.com/page#section"
+        );
+    }
+
+    #[test]
+    fn leaves_urls_without_tracking_untouched() {
+        for url in [
+            "https://example.com/page?b=2&a=1",
+            "https://example.com/page?",
+            "https://example.com/page",
+            "not a url?utm_source=x",
+        ] {
+            assert_eq!(strip(url), url);
+        }
+    }
+
+    #[test]
+    fn applies_user_params_and_exceptions() {
+        let settings = TrackingSettings {
+            enabled: true,
+            params: vec!["ref_*".to_string(), "".to_string()],
+            exceptions: vec!["*.analytics.example.com".to_string()],
+        };
+        assert_eq!(
+            strip_tracking_params("https://example.com/?ref_src=a&ref=b", &settings),
+            "https://example.com/?ref=b"
+        );
+        let exception = "https://app.analytics.example.com/?utm_source=x&ref_src=a";
+        assert_eq!(strip_tracking_params(exception, &settings), exception);
+    }
+
+    #[test]
+    fn browsers_and_rules_can_opt_out() {
+        let ff = browser("ff", "Firefox");
+        let raw = Browser {
+            strip_tracking: false,
+            ..browser("raw", "Raw")
+        };
+        let mut config = Config {
+            browsers: vec![ff.clone(), raw.clone()],
+            rules: vec![
+                Rule {
+                    strip_tracking: false,
+                    ..rule("bad", &invalid_pattern(), "ff")
+                },
+                Rule {
+                    strip_tracking: false,
+                    ..rule("keep", "*://keep.example.com/*", "ff")
+                },
+                rule("other", "*", "ff"),
+            ],
+            ..Default::default()
+        };
+
+        let url = "https://example.com/?utm_source=x";
+        assert!(check_rule(&config, &config.rules[0], url).is_err());
+        assert_eq!(strip_for_browser(&config, &ff, url), "https://example.com/");
+        assert_eq!(strip_for_browser(&config, &raw, url), url);
+        let kept = "https://keep.example.com/?utm_source=x";
+        assert_eq!(strip_for_browser(&config, &ff, kept), kept);
+
+        config.tracking.enabled = false;
+        assert_eq!(strip_for_browser(&config, &ff, url), url);
+    }
+}
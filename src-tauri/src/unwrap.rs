@@ -0,0 +1,144 @@
+use crate::config::Unwrapper;
+use crate::glob_to_regex;
+
+/// Maximum number of nested wrappers to peel off a single URL.
+const MAX_UNWRAP_DEPTH: usize = 5;
+
+/// Built-in redirect wrappers: a glob pattern for the wrapper URL without its
+/// scheme, as both http and https are accepted, and the query parameter holding
+/// the real destination.
+const BUILTIN_UNWRAPPERS: &[(&str, &str)] = &[
+    ("*.safelinks.protection.outlook.com/*", "url"),
+    ("www.google.*/url?*", "q"),
+    ("www.google.*/url?*", "url"),
+    ("google.*/url?*", "q"),
+    ("slack-redir.net/link?*", "url"),
+    ("l.facebook.com/l.php?*", "u"),
+    ("lm.facebook.com/l.php?*", "u"),
+    ("l.instagram.com/?*", "u"),
+    ("www.youtube.com/redirect?*", "q"),
+    ("www.linkedin.com/redir/redirect?*", "url"),
+    ("steamcommunity.com/linkfilter/?*", "url"),
+    ("t.umblr.com/redirect?*", "z"),
+];
+
+/// Follows redirect wrappers (built-in and user-defined) until the URL no
+/// longer matches one, or the depth limit is reached. Returns every URL along
+/// the way, one per wrapper removed; empty if the URL isn't wrapped.
+pub fn unwrap_hops(url: &str, user_unwrappers: &[Unwrapper]) -> Vec<String> {
+    let builtin = BUILTIN_UNWRAPPERS.iter().flat_map(|(pattern, param)| {
+        ["http", "https"].map(|scheme| (format!("{}://{}", scheme, pattern), *param))
+    });
+    let user = user_unwrappers
+        .iter()
+        .map(|u| (u.pattern.clone(), u.param.as_str()));
+
+    // Invalid user patterns are skipped rather than failing the whole lookup
+    let unwrappers: Vec<(regex::Regex, &str)> = user
+        .chain(builtin)
+        .filter_map(|(pattern, param)| Some((glob_to_regex(&pattern).ok()?, param)))
+        .collect();
+
+    let mut hops: Vec<String> = Vec::new();
+    for _ in 0..MAX_UNWRAP_DEPTH {
+        let current = hops.last().map(String::as_str).unwrap_or(url);
+        let target = unwrappers
+            .iter()
+            .filter(|(re, _)| re.is_match(current))
+            .find_map(|(_, param)| query_target(current, param));
+
+        match target {
+            Some(target) => hops.push(target),
+            None => break,
+        }
+    }
+
+    hops
+}
+
+/// Reads `param` from the URL's query string, returning it only if it is
+/// itself an absolute http(s) URL.
+fn query_target(url: &str, param: &str) -> Option<String> {
+    let parsed = url::Url::parse(url).ok()?;
+    let (_, value) = parsed.query_pairs().find(|(key, _)| key == param)?;
+
+    let target = url::Url::parse(&value).ok()?;
+    matches!(target.scheme(), "http" | "https").then(|| value.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrap(wrapper: &str, url: &str) -> String {
+        let encoded: String = url::form_urlencoded::byte_serialize(url.as_bytes()).collect();
+        format!("{}{}", wrapper, encoded)
+    }
+
+    #[test]
+    fn unwraps_builtin_wrappers_over_http_and_https() {
+        let target = "https://example.com/page?a=1&b=2";
+        for wrapper in [
+            "https://eur01.safelinks.protection.outlook.com/?data=x&url=",
+            "https://www.google.com/url?sa=t&q=",
+            "http://www.google.co.uk/url?url=",
+            "http://l.facebook.com/l.php?u=",
+        ] {
+            assert_eq!(
+                unwrap_hops(&wrap(wrapper, target), &[]),
+                [target],
+                "{}",
+                wrapper
+            );
+        }
+    }
+
+    #[test]
+    fn leaves_other_urls_alone() {
+        assert!(unwrap_hops("https://example.com/url?q=https://other.com", &[]).is_empty());
+        // The destination must itself be a web link
+        assert!(unwrap_hops("https://www.google.com/url?q=javascript:alert(1)", &[]).is_empty());
+        assert!(unwrap_hops("https://www.google.com/url?q=relative/path", &[]).is_empty());
+    }
+
+    #[test]
+    fn user_unwrappers_come_first_and_skip_invalid_ones() {
+        let user = [
+            Unwrapper {
+                pattern: "https://track.example.com/*".to_string(),
+                param: "dest".to_string(),
+            },
+            Unwrapper {
+                pattern: "https://www.google.com/url?*".to_string(),
+                param: "target".to_string(),
+            },
+        ];
+        let url = "https://track.example.com/c?dest=https%3A%2F%2Fexample.org%2F";
+        assert_eq!(unwrap_hops(url, &user), ["https://example.org/"]);
+
+        // Built-in wrappers still apply when a user one doesn't find its parameter
+        let url = "https://www.google.com/url?q=https%3A%2F%2Fexample.org%2F";
+        assert_eq!(unwrap_hops(url, &user), ["https://example.org/"]);
+    }
+
+    #[test]
+    fn unwraps_nested_wrappers_up_to_the_depth_limit() {
+        let target = "https://example.com/";
+        let mut url = target.to_string();
+        for _ in 0..2 {
+            url = wrap("https://www.google.com/url?q=", &url);
+        }
+        url = wrap("https://slack-redir.net/link?url=", &url);
+        let hops = unwrap_hops(&url, &[]);
+        assert_eq!(hops.len(), 3);
+        assert_eq!(hops.last().unwrap(), target);
+
+        let mut url = target.to_string();
+        for _ in 0..MAX_UNWRAP_DEPTH + 2 {
+            url = wrap("https://www.google.com/url?q=", &url);
+        }
+        let hops = unwrap_hops(&url, &[]);
+        assert_eq!(hops.len(), MAX_UNWRAP_DEPTH);
+        assert_ne!(hops.last().unwrap(), target);
+    }
+}
@@ -0,0 +1,369 @@
+use crate::config::Config;
+use crate::{config_format, glob_to_regex, pipeline};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// A problem found in the config, e.g. a rule pointing to a browser that no longer exists.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigIssue {
+    pub kind: IssueKind,
+    pub entity: Entity, // The entry the issue is about
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    DanglingReference, // An id that doesn't match anything
+    DuplicateId,       // Two entries of the same type sharing an id
+    MissingIcon,       // A browser icon whose file is gone
+    InvalidPattern,    // A glob or regex that doesn't parse
+    ExtraConfigFile,   // A config file that isn't read, as one in another format is
+}
+
+/// An entry in the config, so the issue can be shown next to it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "id", rename_all = "snake_case")]
+pub enum Entity {
+    Browser(String),
+    Rule(String),
+    App(String),
+    Rewrite(String),
+    Group(String),
+    Unwrapper(usize), // Position in the list, as unwrappers have no id
+    Fallback,
+    ConfigFile(String), // File name, e.g. `config.yaml`
+}
+
+/// Checks the config for dangling references, duplicate ids, missing icon
+/// files, unparsable patterns and config files that are ignored. `config_dir`
+/// is where the config file is, and `icons_dir` where browser icons are kept.
+pub fn validate(config: &Config, config_dir: &Path, icons_dir: &Path) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+    let mut issue = |kind, entity, message: String| {
+        issues.push(ConfigIssue {
+            kind,
+            entity,
+            message,
+        })
+    };
+
+    let browser_exists = |id: &str| config.browsers.iter().any(|b| b.id == id);
+
+    // Dangling references (empty ids mean "none", so are fine)
+    for rule in &config.rules {
+        if !rule.browser_id.is_empty() && !browser_exists(&rule.browser_id) {
+            issue(
+                IssueKind::DanglingReference,
+                Entity::Rule(rule.id.clone()),
+                format!(
+                    "Rule '{}' opens in browser '{}', which doesn't exist",
+                    rule.pattern, rule.browser_id
+                ),
+            );
+        }
+        if !rule.app_id.is_empty() && !config.apps.iter().any(|a| a.id == rule.app_id) {
+            issue(
+                IssueKind::DanglingReference,
+                Entity::Rule(rule.id.clone()),
+                format!(
+                    "Rule '{}' opens in application '{}', which doesn't exist",
+                    rule.pattern, rule.app_id
+                ),
+            );
+        }
+        if !rule.group_id.is_empty() && !config.groups.iter().any(|g| g.id == rule.group_id) {
+            issue(
+                IssueKind::DanglingReference,
+                Entity::Rule(rule.id.clone()),
+                format!(
+                    "Rule '{}' opens in browser group '{}', which doesn't exist",
+                    rule.pattern, rule.group_id
+                ),
+            );
+        }
+    }
+    for group in &config.groups {
+        for id in group.browser_ids.iter().filter(|id| !browser_exists(id)) {
+            issue(
+                IssueKind::DanglingReference,
+                Entity::Group(group.id.clone()),
+                format!(
+                    "Browser group '{}' contains browser '{}', which doesn't exist",
+                    group.name, id
+                ),
+            );
+        }
+    }
+    if !config.fallback.browser_id.is_empty() && !browser_exists(&config.fallback.browser_id) {
+        issue(
+            IssueKind::DanglingReference,
+            Entity::Fallback,
+            format!(
+                "Fallback browser '{}' doesn't exist",
+                config.fallback.browser_id
+            ),
+        );
+    }
+
+    // Duplicate ids
+    let mut check_ids = |what: &str, ids: Vec<&str>, entity: fn(String) -> Entity| {
+        let mut seen = HashSet::new();
+        for id in ids {
+            if !seen.insert(id) {
+                issue(
+                    IssueKind::DuplicateId,
+                    entity(id.to_string()),
+                    format!("More than one {} has the id '{}'", what, id),
+                );
+            }
+        }
+    };
+    check_ids(
+        "browser",
+        config.browsers.iter().map(|b| b.id.as_str()).collect(),
+        Entity::Browser,
+    );
+    check_ids(
+        "rule",
+        config.rules.iter().map(|r| r.id.as_str()).collect(),
+        Entity::Rule,
+    );
+    check_ids(
+        "application",
+        config.apps.iter().map(|a| a.id.as_str()).collect(),
+        Entity::App,
+    );
+    check_ids(
+        "rewrite",
+        config.rewrites.iter().map(|r| r.id.as_str()).collect(),
+        Entity::Rewrite,
+    );
+    check_ids(
+        "browser group",
+        config.groups.iter().map(|g| g.id.as_str()).collect(),
+        Entity::Group,
+    );
+
+    // Missing icon files
+    for browser in &config.browsers {
+        if let Some(ext) = &browser.icon {
+            if !icons_dir.join(format!("{}.{}", browser.id, ext)).is_file() {
+                issue(
+                    IssueKind::MissingIcon,
+                    Entity::Browser(browser.id.clone()),
+                    format!("Icon file for browser '{}' is missing", browser.name),
+                );
+            }
+        }
+    }
+
+    // Unparsable patterns
+    for rule in &config.rules {
+        if let Err(e) = glob_to_regex(&rule.pattern) {
+            issue(
+                IssueKind::InvalidPattern,
+                Entity::Rule(rule.id.clone()),
+                format!("Rule: {}", e),
+            );
+        }
+    }
+    for app in &config.apps {
+        if let Err(e) = regex::Regex::new(&app.pattern) {
+            issue(
+                IssueKind::InvalidPattern,
+                Entity::App(app.id.clone()),
+                format!("Application '{}': invalid pattern: {}", app.name, e),
+            );
+        }
+    }
+    for rewrite in &config.rewrites {
+        if let Err(e) = pipeline::validate_rewrite(&rewrite.pattern, &rewrite.replacement) {
+            issue(
+                IssueKind::InvalidPattern,
+                Entity::Rewrite(rewrite.id.clone()),
+                e,
+            );
+        }
+    }
+    for (i, unwrapper) in config.unwrappers.iter().enumerate() {
+        if let Err(e) = glob_to_regex(&unwrapper.pattern) {
+            issue(
+                IssueKind::InvalidPattern,
+                Entity::Unwrapper(i),
+                format!("Unwrapper: {}", e),
+            );
+        }
+    }
+
+    // Config files in more than one format, of which only the first is read
+    let files = config_format::config_files(config_dir);
+    let file_name = |path: &Path| {
+        let name = path.file_name().unwrap_or_default();
+        name.to_string_lossy().into_owned()
+    };
+    if let Some((read, ignored)) = files.split_first() {
+        for path in ignored {
+            issue(
+                IssueKind::ExtraConfigFile,
+                Entity::ConfigFile(file_name(path)),
+                format!(
+                    "'{}' is ignored, as '{}' is read instead",
+                    file_name(path),
+                    file_name(read)
+                ),
+            );
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_support::{self, app, browser, invalid_pattern, rule};
+    use crate::config::{BrowserGroup, RewriteRule, Rule};
+    use std::fs;
+
+    fn issues(config: &Config, icons_dir: &Path) -> Vec<(IssueKind, Entity)> {
+        validate(config, Path::new("/nonexistent"), icons_dir)
+            .into_iter()
+            .map(|issue| (issue.kind, issue.entity))
+            .collect()
+    }
+
+    fn group(id: &str, browser_ids: &[&str]) -> BrowserGroup {
+        BrowserGroup {
+            id: id.to_string(),
+            name: id.to_string(),
+            browser_ids: browser_ids.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_configs_have_no_issues() {
+        let mut config = test_support::config();
+        config.rules = vec![rule("r1", "*://example.com/*", "ff")];
+        config.groups = vec![group("g1", &["ff", "work"])];
+        config.fallback.browser_id = "ff".to_string();
+        assert!(issues(&config, Path::new("/nonexistent")).is_empty());
+    }
+
+    #[test]
+    fn dangling_references_name_the_entry() {
+        let mut config = test_support::config();
+        config.rules = vec![
+            rule("r1", "*", "gone"),
+            Rule {
+                app_id: "gone".to_string(),
+                ..rule("r2", "*", "")
+            },
+            Rule {
+                group_id: "gone".to_string(),
+                ..rule("r3", "*", "")
+            },
+            rule("r4", "*", ""),
+        ];
+        config.groups = vec![group("g1", &["gone"])];
+        config.fallback.browser_id = "gone".to_string();
+
+        let found = issues(&config, Path::new("/nonexistent"));
+        assert!(found
+            .iter()
+            .all(|(kind, _)| matches!(kind, IssueKind::DanglingReference)));
+        assert_eq!(
+            found
+                .into_iter()
+                .map(|(_, entity)| entity)
+                .collect::<Vec<_>>(),
+            [
+                Entity::Rule("r1".to_string()),
+                Entity::Rule("r2".to_string()),
+                Entity::Rule("r3".to_string()),
+                Entity::Group("g1".to_string()),
+                Entity::Fallback,
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_ids_are_reported_once_per_repeat() {
+        let mut config = test_support::config();
+        config.browsers.push(browser("ff", "Firefox again"));
+        config.rules = vec![rule("r1", "*", "ff"); 3];
+
+        let found = issues(&config, Path::new("/nonexistent"));
+        assert!(matches!(
+            &found[..],
+            [
+                (IssueKind::DuplicateId, Entity::Browser(b)),
+                (IssueKind::DuplicateId, Entity::Rule(r1)),
+                (IssueKind::DuplicateId, Entity::Rule(r2)),
+            ] if b == "ff" && r1 == "r1" && r2 == "r1"
+        ));
+    }
+
+    #[test]
+    fn missing_icon_files_are_reported() {
+        let dir =
+            std::env::temp_dir().join(format!("pick-browser-validate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("ff.png"), b"").unwrap();
+
+        let mut config = test_support::config();
+        for browser in &mut config.browsers {
+            browser.icon = Some("png".to_string());
+        }
+        let found = issues(&config, &dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(
+            &found[..],
+            [(IssueKind::MissingIcon, Entity::Browser(id))] if id == "work"
+        ));
+    }
+
+    #[test]
+    fn config_files_in_other_formats_are_reported() {
+        let dir = std::env::temp_dir().join(format!("pick-browser-formats-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config.json"), b"{}").unwrap();
+
+        let config = Config::default();
+        assert!(validate(&config, &dir, &dir).is_empty());
+
+        fs::write(dir.join("config.toml"), b"").unwrap();
+        let found = validate(&config, &dir, &dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(
+            &found[..],
+            [ConfigIssue { kind: IssueKind::ExtraConfigFile, entity: Entity::ConfigFile(name), .. }]
+                if name == "config.json"
+        ));
+    }
+
+    #[test]
+    fn invalid_patterns_are_reported() {
+        let mut config = test_support::config();
+        config.rules = vec![rule("r1", &invalid_pattern(), "ff")];
+        config.apps = vec![app("a1", "(", "zoom")];
+        config.rewrites = vec![RewriteRule {
+            id: "w1".to_string(),
+            pattern: "a".to_string(),
+            replacement: "$2".to_string(),
+        }];
+
+        let found = issues(&config, Path::new("/nonexistent"));
+        assert!(matches!(
+            &found[..],
+            [
+                (IssueKind::InvalidPattern, Entity::Rule(r)),
+                (IssueKind::InvalidPattern, Entity::App(a)),
+                (IssueKind::InvalidPattern, Entity::Rewrite(w)),
+            ] if r == "r1" && a == "a1" && w == "w1"
+        ));
+    }
+}
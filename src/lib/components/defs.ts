@@ -3,6 +3,10 @@ export type Browser = {
     name: string;
     path: string;
     icon: string | null; // File extension if icon exists
+    strip_tracking: boolean; // Strip tracking parameters from URLs opened in this browser
+    schemes: Array<string>; // URL schemes this target handles (empty = web links and local files)
+    hidden: boolean; // Left out of the picker, but still usable by rules
+    locked?: boolean; // From the system config, so can't be changed or deleted
 };
 
 export type BrowserIcon = {
@@ -14,4 +18,167 @@ export type Rule = {
     readonly id: string;
     pattern: string; // Glob pattern to match against URLs (* = wildcard)
     browser_id: string; // ID of the browser to open matching URLs in
+    strip_tracking: boolean; // Strip tracking parameters from URLs matching this rule
+    app_id: string; // Application target to open matching URLs in instead (empty = none)
+    group_id: string; // Browser group to open matching URLs in all at once (empty = none)
+    locked?: boolean; // From the system config, so can't be changed or deleted
 };
+
+// A named set of browsers that a URL can be opened in all at once
+export type BrowserGroup = {
+    readonly id: string;
+    name: string;
+    browser_ids: Array<string>;
+};
+
+// Everything that would stop opening URLs in a browser if it were deleted
+export type BrowserUsage = {
+    rules: Array<Rule>; // Rules that open URLs in it
+    group_rules: Array<Rule>; // Rules that open URLs in a group it is in
+    groups: Array<string>; // Names of the groups it is in
+    fallback: boolean; // Whether it is the fallback browser
+    override_active: boolean; // Whether a temporary override opens everything in it
+};
+
+export type LaunchResult = {
+    browser_id: string;
+    error: string | null; // Set if the browser failed to launch
+};
+
+export type AppTarget = {
+    readonly id: string;
+    name: string;
+    pattern: string; // Regex matched against the URL
+    template: string; // URI built from the captures, e.g. zoommtg://zoom.us/join?confno=$1
+    command: string; // Executable to launch with the URI (empty = the system's handler)
+};
+
+// Where a rule sends matching URLs, as returned by check_rules
+export type RuleTarget =
+    | { kind: "browser"; id: string }
+    | { kind: "app"; id: string }
+    | { kind: "group"; id: string };
+
+export type RuleSuggestion = {
+    origin: string;
+    pattern: string; // Glob pattern the accepted rule would use
+    browser_id: string;
+    count: number; // Number of times this origin was manually opened in the browser
+};
+
+export type RewriteRule = {
+    readonly id: string;
+    pattern: string; // Regex matched against the URL
+    replacement: string; // Replacement text; $1, ${name} refer to capture groups
+};
+
+export type PipelineStep = {
+    stage: "unwrap" | "expand" | "rewrite" | "rewrite_skipped";
+    description: string;
+    url: string; // The URL after this step
+};
+
+export type PipelineResult = {
+    original: string;
+    url: string; // The URL after all steps, as seen by the routing rules
+    steps: Array<PipelineStep>;
+};
+
+// How one rule was evaluated by explain_url
+export type RuleStep = {
+    rule_id: string;
+    pattern: string;
+    outcome: "matched" | "not_matched" | "skipped" | "invalid" | "not_consulted";
+    reason: string;
+};
+
+// Everything that decides where a URL opens, as returned by explain_url
+export type Explanation = {
+    pipeline: PipelineResult; // Unwrapping, expansion and rewrites applied before the rules
+    override_active: TemporaryOverride | null; // Override used instead of the rules
+    rules: Array<RuleStep>; // Every rule, in the order they are checked
+    winner: string | null; // ID of the rule that decided, if any
+    target: RuleTarget | null; // Where the URL opens (null = prompt to choose)
+    summary: string;
+};
+
+export type FallbackSettings = {
+    browser_id: string; // Default browser (empty = none)
+    countdown_secs: number; // Seconds before opening automatically (0 = never)
+};
+
+export type CountdownTick = {
+    browser_id: string; // Browser the URL will open in
+    remaining: number; // Seconds left
+};
+
+export type CountdownFinished = {
+    error: string | null; // Set if the browser failed to launch
+};
+
+export type TemporaryOverride = {
+    browser_id: string; // Browser to open everything in (empty = always prompt)
+    expires_at: number; // Unix timestamp, in seconds
+};
+
+// An entry in the config; unwrappers have no id, so are identified by their position
+export type ConfigEntity =
+    | { type: "browser" | "rule" | "app" | "rewrite" | "group"; id: string }
+    | { type: "unwrapper"; id: number }
+    | { type: "fallback" }
+    | { type: "config_file"; id: string }; // File name, e.g. config.yaml
+
+// A problem found in the config by validate_config
+export type ConfigIssue = {
+    kind:
+        | "dangling_reference"
+        | "duplicate_id"
+        | "missing_icon"
+        | "invalid_pattern"
+        | "extra_config_file";
+    entity: ConfigEntity; // The entry the issue is about
+    message: string;
+};
+
+// What clean_up_icons changed
+export type IconCleanup = {
+    removed_files: Array<string>; // Icon files no browser used
+    repaired_browsers: Array<string>; // IDs of browsers whose icon file was missing
+};
+
+// A problem with a rule caused by an earlier rule, found by analyze_rules
+export type RuleWarning = {
+    rule_id: string;
+    kind: "duplicate" | "shadowed" | "overlap";
+    other_rule_id: string; // The earlier rule causing the problem
+    message: string;
+};
+
+// A browser in an import whose executable isn't on this machine
+export type MissingPath = {
+    browser_id: string; // ID in the export, which path remaps are keyed by
+    name: string;
+    path: string;
+};
+
+// What import_config changed, or would change on a dry run
+export type ImportReport = {
+    added: Array<string>;
+    skipped: Array<string>; // Entries already set up here, or that couldn't be imported
+    renamed_ids: Array<string>; // Entries given a new id, as theirs was already taken here
+    remapped_paths: Array<string>; // Browsers found at a different path on this machine
+    missing_paths: Array<MissingPath>;
+};
+
+// Other browser pickers import_rules can read the config of
+export type MigrationSource = "finicky" | "browserosaurus" | "choosy" | "junction";
+
+// What import_rules changed, or would change on a dry run
+export type MigrationReport = {
+    added: Array<string>;
+    skipped: Array<string>; // Entries already set up here
+    unconverted: Array<string>; // Constructs that have no equivalent here
+};
+
+// File formats the config can be written in, for convert_config
+export type ConfigFormat = "json" | "toml" | "yaml";
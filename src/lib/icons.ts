@@ -0,0 +1,33 @@
+import { convertFileSrc } from "@tauri-apps/api/core";
+
+/** File types accepted for browser icons, for a file input's `accept` attribute. */
+export const ICON_ACCEPT =
+    "image/png, image/jpeg, image/webp, image/svg+xml, .svg, .ico, .icns";
+
+const MIME_BY_EXTENSION: Record<string, string> = {
+    png: "image/png",
+    jpg: "image/jpeg",
+    jpeg: "image/jpeg",
+    webp: "image/webp",
+    svg: "image/svg+xml",
+    ico: "image/x-icon",
+    icns: "image/icns",
+};
+
+/**
+ * Get the MIME type of an icon file. Browsers often report ICO and ICNS files
+ * with an empty or nonstandard type, so the extension is used for those.
+ */
+export function iconMimeType(file: File): string {
+    const ext = file.name.split(".").pop()?.toLowerCase() ?? "";
+    return MIME_BY_EXTENSION[ext] ?? file.type;
+}
+
+/**
+ * Get the URL a browser's icon is served at by the `pickicon` protocol. `size` is the pixel size
+ * it's shown at, so a suitably sized file is picked; without it the largest is served.
+ */
+export function browserIconUrl(id: string, size?: number): string {
+    const url = convertFileSrc(id, "pickicon");
+    return size === undefined ? url : `${url}?size=${size}`;
+}